@@ -1,15 +1,31 @@
 use crate::impl_::cell::Cell as CellImpl;
-use crate::impl_::lambda::IsLambda1;
-use crate::impl_::lambda::IsLambda2;
-use crate::impl_::lambda::IsLambda3;
-use crate::impl_::lambda::IsLambda4;
-use crate::impl_::lambda::IsLambda5;
-use crate::impl_::lambda::IsLambda6;
+use crate::impl_::lambda::{IsLambda1, IsLambda2, IsLambda3, IsLambda4, IsLambda5, IsLambda6};
+use crate::impl_::lambda::{IsLambda7, IsLambda8, IsLambda9, IsLambda10, IsLambda11, IsLambda12};
 use crate::impl_::lazy::Lazy;
 use crate::listener::Listener;
 use crate::sodium_ctx::SodiumCtx;
 use crate::stream::Stream;
 use crate::Dep;
+use crate::SodiumError;
+use crate::Traceable;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::time::Duration;
+
+macro_rules! lift {
+    ($lift:ident, $is_lambda:ident, [$($T:ident $c:ident),*], $R:ident) => {
+        #[allow(clippy::too_many_arguments)]
+        pub fn $lift<
+            $($T:Clone+Send+'static,)*
+            $R:Clone+Send+'static,
+            FN:$is_lambda<A,$($T,)* $R>+Send+'static
+        >(&self, $($c: &Cell<$T>,)* f: FN) -> Cell<$R> {
+            Cell { impl_: self.impl_.$lift($(&$c.impl_,)* f) }
+        }
+    };
+}
 
 pub struct Cell<A> {
     pub impl_: CellImpl<A>
@@ -21,15 +37,28 @@ impl<A> Clone for Cell<A> {
     }
 }
 
+impl<A> Traceable for Cell<A> {
+    fn deps(&self) -> Vec<Dep> {
+        vec![self.impl_.to_dep()]
+    }
+}
+
 impl<A:Clone+Send+'static> Cell<A> {
     pub fn new(sodium_ctx: &SodiumCtx, value: A) -> Cell<A> {
         Cell { impl_: CellImpl::new(&sodium_ctx.impl_, value) }
     }
 
     pub fn sample(&self) -> A {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "sampled cell");
         self.impl_.sample()
     }
 
+    // like sample, but returns an error instead of panicking, e.g. on a CellLoop that is not looped yet
+    pub fn try_sample(&self) -> Result<A,SodiumError> {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "sampled cell");
+        self.impl_.try_sample()
+    }
+
     pub fn sample_lazy(&self) -> Lazy<A> {
         self.impl_.sample_lazy()
     }
@@ -43,33 +72,52 @@ impl<A:Clone+Send+'static> Cell<A> {
         Stream { impl_: self.impl_.updates() }
     }
 
-    pub fn value(&self) -> Stream<A> {
-        Stream { impl_: self.impl_.value() }
+    // labels this cell's entries in SodiumCtx::profile_report, to tell it apart from other cells
+    // made by the same operator
+    pub fn named(&self, label: &str) -> Cell<A> {
+        Cell { impl_: self.impl_.named(label) }
     }
 
-    pub fn map<B:Clone+Send+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, f: FN) -> Cell<B> {
-        Cell { impl_: self.impl_.map(f) }
+    pub fn value(&self) -> Stream<A> {
+        Stream { impl_: self.impl_.value() }
     }
 
-    pub fn lift2<B:Clone+Send+'static,C:Clone+Send+'static,FN:IsLambda2<A,B,C>+Send+'static>(&self, cb: &Cell<B>, f: FN) -> Cell<C> {
-        Cell { impl_: self.impl_.lift2(&cb.impl_, f) }
+    // The result of the future f makes for the current value, None while it is still running. Results
+    // for values that have since changed are dropped, see Stream::map_async. Panics if no spawner
+    // has been set.
+    pub fn map_async<B,FUT,FN>(&self, f: FN) -> Cell<Option<B>>
+        where B: Send + Clone + 'static,
+              FUT: Future<Output=B> + Send + 'static,
+              FN: IsLambda1<A,FUT> + Send + Sync + 'static
+    {
+        Cell { impl_: self.impl_.map_async(f) }
     }
 
-    pub fn lift3<B:Clone+Send+'static,C:Clone+Send+'static,D:Clone+Send+'static,FN:IsLambda3<A,B,C,D>+Send+'static>(&self, cb: &Cell<B>, cc: &Cell<C>, f: FN) -> Cell<D> {
-        Cell { impl_: self.impl_.lift3(&cb.impl_, &cc.impl_, f) }
+    // the value every period, driven by SodiumCtx::run_timers
+    pub fn sample_every(&self, period: Duration) -> Stream<A> {
+        Stream { impl_: self.impl_.sample_every(period) }
     }
 
-    pub fn lift4<B:Clone+Send+'static,C:Clone+Send+'static,D:Clone+Send+'static,E:Clone+Send+'static,FN:IsLambda4<A,B,C,D,E>+Send+'static>(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, f: FN) -> Cell<E> {
-        Cell { impl_: self.impl_.lift4(&cb.impl_, &cc.impl_, &cd.impl_, f) }
+    pub fn map<B:Clone+Send+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, f: FN) -> Cell<B> {
+        Cell { impl_: self.impl_.map(f) }
     }
 
-    pub fn lift5<B:Clone+Send+'static,C:Clone+Send+'static,D:Clone+Send+'static,E:Clone+Send+'static,F:Clone+Send+'static,FN:IsLambda5<A,B,C,D,E,F>+Send+'static>(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, ce: &Cell<E>, f: FN) -> Cell<F> {
-        Cell { impl_: self.impl_.lift5(&cb.impl_, &cc.impl_, &cd.impl_, &ce.impl_, f) }
+    // like map, but f is not called on the current value until the new cell is sampled
+    pub fn map_lazy<B:Clone+Send+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, f: FN) -> Cell<B> {
+        Cell { impl_: self.impl_.map_lazy(f) }
     }
 
-    pub fn lift6<B:Clone+Send+'static,C:Clone+Send+'static,D:Clone+Send+'static,E:Clone+Send+'static,F:Clone+Send+'static,G:Clone+Send+'static,FN:IsLambda6<A,B,C,D,E,F,G>+Send+'static>(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, ce: &Cell<E>, cf: &Cell<F>, f: FN) -> Cell<G> {
-        Cell { impl_: self.impl_.lift6(&cb.impl_, &cc.impl_, &cd.impl_, &ce.impl_, &cf.impl_, f) }
-    }
+    lift!(lift2, IsLambda2, [B cb], C);
+    lift!(lift3, IsLambda3, [B cb, C cc], D);
+    lift!(lift4, IsLambda4, [B cb, C cc, D cd], E);
+    lift!(lift5, IsLambda5, [B cb, C cc, D cd, E ce], F);
+    lift!(lift6, IsLambda6, [B cb, C cc, D cd, E ce, F cf], G);
+    lift!(lift7, IsLambda7, [B cb, C cc, D cd, E ce, F cf, G cg], H);
+    lift!(lift8, IsLambda8, [B cb, C cc, D cd, E ce, F cf, G cg, H ch], I);
+    lift!(lift9, IsLambda9, [B cb, C cc, D cd, E ce, F cf, G cg, H ch, I ci], J);
+    lift!(lift10, IsLambda10, [B cb, C cc, D cd, E ce, F cf, G cg, H ch, I ci, J cj], K);
+    lift!(lift11, IsLambda11, [B cb, C cc, D cd, E ce, F cf, G cg, H ch, I ci, J cj, K ck], L);
+    lift!(lift12, IsLambda12, [B cb, C cc, D cd, E ce, F cf, G cg, H ch, I ci, J cj, K ck, L cl], M);
 
     pub fn switch_s(csa: &Cell<Stream<A>>) -> Stream<A> {
         Stream { impl_: CellImpl::switch_s(&csa.map(|sa: &Stream<A>| sa.impl_.clone()).impl_) }
@@ -79,11 +127,31 @@ impl<A:Clone+Send+'static> Cell<A> {
         Cell { impl_: CellImpl::switch_c(&cca.map(|ca: &Cell<A>| ca.impl_.clone()).impl_) }
     }
 
+    // merges a keyed collection of streams, firing the values of every inner stream that fired by key
+    pub fn switch_s_keyed<K:Eq+Hash+Clone+Send+'static>(csa: &Cell<HashMap<K,Stream<A>>>) -> Stream<HashMap<K,A>> {
+        Stream {
+            impl_: CellImpl::switch_s_keyed(
+                &csa.map(|sa: &HashMap<K,Stream<A>>| sa.iter().map(|(k, sa): (&K, &Stream<A>)| (k.clone(), sa.impl_.clone())).collect::<HashMap<_,_>>()).impl_
+            )
+        }
+    }
+
+    // collects a keyed list of cells into a cell of their values, in list order
+    pub fn switch_c_keyed<K:Clone+Send+'static>(cca: &Cell<Vec<(K,Cell<A>)>>) -> Cell<Vec<(K,A)>> {
+        Cell {
+            impl_: CellImpl::switch_c_keyed(
+                &cca.map(|ca: &Vec<(K,Cell<A>)>| ca.iter().map(|(k, ca): &(K,Cell<A>)| (k.clone(), ca.impl_.clone())).collect::<Vec<_>>()).impl_
+            )
+        }
+    }
+
     pub fn listen_weak<K: FnMut(&A)+Send+Sync+'static>(&self, k: K) -> Listener {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "listened to cell");
         Listener { impl_: self.impl_.listen_weak(k) }
     }
 
     pub fn listen<K:IsLambda1<A,()>+Send+Sync+'static>(&self, k: K) -> Listener {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "listened to cell");
         Listener { impl_: self.impl_.listen(k) }
     }
 }
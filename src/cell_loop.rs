@@ -1,5 +1,6 @@
 use crate::Cell;
 use crate::SodiumCtx;
+use crate::SodiumError;
 use crate::impl_::cell_loop::CellLoop as CellLoopImpl;
 
 pub struct CellLoop<A> {
@@ -29,4 +30,8 @@ impl<A:Send+Clone+'static> CellLoop<A> {
     pub fn loop_(&self, ca: &Cell<A>) {
         self.impl_.loop_(&ca.impl_);
     }
+
+    pub fn try_loop(&self, ca: &Cell<A>) -> Result<(),SodiumError> {
+        self.impl_.try_loop(&ca.impl_)
+    }
 }
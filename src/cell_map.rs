@@ -0,0 +1,371 @@
+use crate::Cell;
+use crate::IsLambda2;
+use crate::IsLambda3;
+use crate::SodiumCtx;
+use crate::Stream;
+use crate::StreamSink;
+use crate::impl_::lambda::{lambda1, lambda2, lambda2_deps, lambda3_deps};
+use crate::impl_::shared_tree::SharedTree;
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapDiff<K,V> {
+    Insert { key: K, value: V },
+    Remove { key: K, value: V },
+    Update { key: K, old: V, new: V }
+}
+
+impl<K:Ord+Clone,V:Clone> MapDiff<K,V> {
+    pub fn key(&self) -> &K {
+        match self {
+            MapDiff::Insert { key, .. } => key,
+            MapDiff::Remove { key, .. } => key,
+            MapDiff::Update { key, .. } => key
+        }
+    }
+
+    pub fn apply(&self, map: &mut SharedMap<K,V>) {
+        match self {
+            MapDiff::Insert { key, value } => { map.insert(key.clone(), value.clone()); },
+            MapDiff::Remove { key, .. } => { map.remove(key); },
+            MapDiff::Update { key, new, .. } => { map.insert(key.clone(), new.clone()); }
+        }
+    }
+}
+
+// The value of a CellMap's cell, a sorted map that shares its storage with its clones, see SharedVec.
+pub struct SharedMap<K,V> {
+    tree: SharedTree<(K,V)>
+}
+
+impl<K,V> Clone for SharedMap<K,V> {
+    fn clone(&self) -> Self {
+        SharedMap {
+            tree: self.tree.clone()
+        }
+    }
+}
+
+impl<K:Ord+Clone,V:Clone> SharedMap<K,V> {
+
+    pub fn new() -> SharedMap<K,V> {
+        SharedMap { tree: SharedTree::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.len() == 0
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.position(key) {
+            Ok(index) => self.tree.get(index).map(|(_, v)| v),
+            Err(_) => None
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.position(key).is_ok()
+    }
+
+    // in key order
+    pub fn iter(&self) -> impl Iterator<Item=(&K,&V)> {
+        self.tree.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item=&K> {
+        self.tree.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item=&V> {
+        self.tree.iter().map(|(_, v)| v)
+    }
+
+    pub fn to_map(&self) -> BTreeMap<K,V> {
+        self.tree.iter().cloned().collect()
+    }
+
+    // returns the old value if the key was already present
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.position(&key) {
+            Ok(index) => {
+                let old = self.tree.get(index).map(|(_, v)| v.clone());
+                self.tree.set(index, (key, value), 0);
+                old
+            },
+            Err(index) => {
+                self.tree.insert(index, (key, value), 0);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match self.position(key) {
+            Ok(index) => Some(self.tree.remove(index).1),
+            Err(_) => None
+        }
+    }
+
+    fn position(&self, key: &K) -> Result<usize,usize> {
+        self.tree.search_by(|(k, _)| k.cmp(key))
+    }
+}
+
+impl<K:Ord+Clone,V:Clone> Default for SharedMap<K,V> {
+    fn default() -> Self {
+        SharedMap::new()
+    }
+}
+
+impl<K:Ord+Clone,V:Clone> From<BTreeMap<K,V>> for SharedMap<K,V> {
+    fn from(map: BTreeMap<K,V>) -> Self {
+        SharedMap { tree: SharedTree::from_vec(map.into_iter().map(|entry| (entry, 0)).collect()) }
+    }
+}
+
+impl<K:Ord+Clone,V:Clone> FromIterator<(K,V)> for SharedMap<K,V> {
+    fn from_iter<I:IntoIterator<Item=(K,V)>>(iter: I) -> Self {
+        SharedMap::from(iter.into_iter().collect::<BTreeMap<K,V>>())
+    }
+}
+
+impl<K:Ord+Clone,V:Clone> Index<&K> for SharedMap<K,V> {
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("key not found in SharedMap")
+    }
+}
+
+impl<K:Ord+Clone,V:Clone+PartialEq> PartialEq for SharedMap<K,V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K:Ord+Clone+fmt::Debug,V:Clone+fmt::Debug> fmt::Debug for SharedMap<K,V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// The keyed counterpart of CellVec: a SharedMap held in a cell, along with a stream of the diffs
+// made to it in each transaction.
+pub struct CellMap<K,V> {
+    sodium_ctx: SodiumCtx,
+    cell: Cell<SharedMap<K,V>>,
+    diffs: Stream<Vec<MapDiff<K,V>>>
+}
+
+impl<K,V> Clone for CellMap<K,V> {
+    fn clone(&self) -> Self {
+        CellMap {
+            sodium_ctx: SodiumCtx { impl_: self.sodium_ctx.impl_.clone() },
+            cell: self.cell.clone(),
+            diffs: self.diffs.clone()
+        }
+    }
+}
+
+impl<K:Ord+Clone+Send+Sync+'static,V:Clone+Send+Sync+'static> CellMap<K,V> {
+
+    // diffs must be valid against init, and against the result of each earlier firing
+    pub fn new(sodium_ctx: &SodiumCtx, init: SharedMap<K,V>, diffs: &Stream<Vec<MapDiff<K,V>>>) -> CellMap<K,V> {
+        let cell = diffs.accum(init, |diffs: &Vec<MapDiff<K,V>>, map: &SharedMap<K,V>| {
+            let mut map = map.clone();
+            for diff in diffs {
+                diff.apply(&mut map);
+            }
+            map
+        });
+        CellMap {
+            sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+            cell,
+            diffs: diffs.clone()
+        }
+    }
+
+    pub fn cell(&self) -> Cell<SharedMap<K,V>> {
+        self.cell.clone()
+    }
+
+    pub fn diffs(&self) -> Stream<Vec<MapDiff<K,V>>> {
+        self.diffs.clone()
+    }
+
+    pub fn map<W:Clone+Send+Sync+'static,FN:IsLambda2<K,V,W>+Send+Sync+'static>(&self, mut f: FN) -> CellMap<K,W> {
+        self.sodium_ctx.transaction(|| {
+            let f_deps = lambda2_deps(&f);
+            let init: SharedMap<K,W> = self.cell.sample().iter().map(|(k, v)| (k.clone(), f.call(k, v))).collect();
+            // the mapped values are kept so removes and updates can report the old value without calling f again
+            let mapped = Arc::new(Mutex::new(init.clone()));
+            let diffs = self.diffs.map(lambda1(move |diffs: &Vec<MapDiff<K,V>>| {
+                let mut l = mapped.lock();
+                let mapped: &mut SharedMap<K,W> = l.as_mut().unwrap();
+                let mut result = Vec::with_capacity(diffs.len());
+                for diff in diffs {
+                    let diff2 = match diff {
+                        MapDiff::Insert { key, value } => MapDiff::Insert { key: key.clone(), value: f.call(key, value) },
+                        MapDiff::Remove { key, .. } => MapDiff::Remove { key: key.clone(), value: mapped[key].clone() },
+                        MapDiff::Update { key, new, .. } => MapDiff::Update { key: key.clone(), old: mapped[key].clone(), new: f.call(key, new) }
+                    };
+                    diff2.apply(mapped);
+                    result.push(diff2);
+                }
+                result
+            }, f_deps));
+            CellMap::new(&self.sodium_ctx, init, &diffs)
+        })
+    }
+
+    pub fn filter<PRED:IsLambda2<K,V,bool>+Send+Sync+'static>(&self, mut pred: PRED) -> CellMap<K,V> {
+        self.sodium_ctx.transaction(|| {
+            let pred_deps = lambda2_deps(&pred);
+            let init: SharedMap<K,V> = self.cell.sample().iter().filter(|(k, v)| pred.call(k, v)).map(|(k, v)| (k.clone(), v.clone())).collect();
+            let kept: Arc<Mutex<BTreeSet<K>>> = Arc::new(Mutex::new(init.keys().cloned().collect()));
+            let diffs = self.diffs.map(lambda1(move |diffs: &Vec<MapDiff<K,V>>| {
+                let mut l = kept.lock();
+                let kept: &mut BTreeSet<K> = l.as_mut().unwrap();
+                let mut result = Vec::new();
+                for diff in diffs {
+                    match diff {
+                        MapDiff::Insert { key, value } => {
+                            if pred.call(key, value) {
+                                kept.insert(key.clone());
+                                result.push(diff.clone());
+                            }
+                        },
+                        MapDiff::Remove { key, .. } => {
+                            if kept.remove(key) {
+                                result.push(diff.clone());
+                            }
+                        },
+                        MapDiff::Update { key, old, new } => {
+                            let was_kept = kept.contains(key);
+                            let keep = pred.call(key, new);
+                            match (was_kept, keep) {
+                                (true, true) => result.push(diff.clone()),
+                                (true, false) => {
+                                    kept.remove(key);
+                                    result.push(MapDiff::Remove { key: key.clone(), value: old.clone() });
+                                },
+                                (false, true) => {
+                                    kept.insert(key.clone());
+                                    result.push(MapDiff::Insert { key: key.clone(), value: new.clone() });
+                                },
+                                (false, false) => ()
+                            }
+                        }
+                    }
+                }
+                result
+            }, pred_deps)).filter(|diffs: &Vec<MapDiff<K,V>>| !diffs.is_empty());
+            CellMap::new(&self.sodium_ctx, init, &diffs)
+        })
+    }
+
+    // Folds the entries into a single value, see CellVec::fold.
+    pub fn fold<B,INSERT,REMOVE>(&self, init: B, mut insert: INSERT, mut remove: REMOVE) -> Cell<B>
+        where B: Clone + Send + 'static,
+              INSERT: IsLambda3<B,K,V,B> + Send + Sync + 'static,
+              REMOVE: IsLambda3<B,K,V,B> + Send + Sync + 'static
+    {
+        self.sodium_ctx.transaction(|| {
+            let mut deps = lambda3_deps(&insert);
+            deps.append(&mut lambda3_deps(&remove));
+            let init = self.cell.sample().iter().fold(init, |b: B, (k, v)| insert.call(&b, k, v));
+            self.diffs.accum(init, lambda2(move |diffs: &Vec<MapDiff<K,V>>, b: &B| {
+                let mut b = b.clone();
+                for diff in diffs {
+                    b = match diff {
+                        MapDiff::Insert { key, value } => insert.call(&b, key, value),
+                        MapDiff::Remove { key, value } => remove.call(&b, key, value),
+                        MapDiff::Update { key, old, new } => {
+                            let b = remove.call(&b, key, old);
+                            insert.call(&b, key, new)
+                        }
+                    };
+                }
+                b
+            }, deps))
+        })
+    }
+}
+
+// Edits a CellMap, see CellVecSink.
+pub struct CellMapSink<K,V> {
+    sink: StreamSink<Vec<MapDiff<K,V>>>,
+    map: Arc<Mutex<SharedMap<K,V>>>,
+    cell_map: CellMap<K,V>
+}
+
+impl<K,V> Clone for CellMapSink<K,V> {
+    fn clone(&self) -> Self {
+        CellMapSink {
+            sink: self.sink.clone(),
+            map: self.map.clone(),
+            cell_map: self.cell_map.clone()
+        }
+    }
+}
+
+impl<K:Ord+Clone+Send+Sync+'static,V:Clone+Send+Sync+'static> CellMapSink<K,V> {
+
+    pub fn new(sodium_ctx: &SodiumCtx, init: SharedMap<K,V>) -> CellMapSink<K,V> {
+        let sink = StreamSink::new_with_coalescer(sodium_ctx, |diffs1: &Vec<MapDiff<K,V>>, diffs2: &Vec<MapDiff<K,V>>| {
+            let mut diffs = diffs1.clone();
+            diffs.extend(diffs2.iter().cloned());
+            diffs
+        });
+        let cell_map = CellMap::new(sodium_ctx, init.clone(), &sink.stream());
+        CellMapSink {
+            sink,
+            map: Arc::new(Mutex::new(init)),
+            cell_map
+        }
+    }
+
+    pub fn cell_map(&self) -> CellMap<K,V> {
+        self.cell_map.clone()
+    }
+
+    // inserts the entry, or updates it if the key is already present
+    pub fn insert(&self, key: K, value: V) {
+        let diff;
+        {
+            let mut l = self.map.lock();
+            let map: &mut SharedMap<K,V> = l.as_mut().unwrap();
+            diff = match map.get(&key) {
+                Some(old) => MapDiff::Update { key, old: old.clone(), new: value },
+                None => MapDiff::Insert { key, value }
+            };
+            diff.apply(map);
+        }
+        self.sink.send(vec![diff]);
+    }
+
+    // does nothing if the key is not present
+    pub fn remove(&self, key: &K) {
+        let diff;
+        {
+            let mut l = self.map.lock();
+            let map: &mut SharedMap<K,V> = l.as_mut().unwrap();
+            match map.remove(key) {
+                Some(value) => diff = MapDiff::Remove { key: key.clone(), value },
+                None => return
+            }
+        }
+        self.sink.send(vec![diff]);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::impl_::cell_sink::CellSink as CellSinkImpl;
 use crate::sodium_ctx::SodiumCtx;
 use crate::cell::Cell;
+use crate::Dep;
+use crate::SodiumError;
+use crate::Traceable;
 
 pub struct CellSink<A> {
     pub impl_: CellSinkImpl<A>
@@ -14,11 +17,34 @@ impl<A> Clone for CellSink<A> {
     }
 }
 
+impl<A:Clone+Send+'static> Traceable for CellSink<A> {
+    fn deps(&self) -> Vec<Dep> {
+        vec![self.impl_.cell().to_dep()]
+    }
+}
+
 impl<A:Clone+Send+'static> CellSink<A> {
     pub fn new(sodium_ctx: &SodiumCtx, a: A) -> CellSink<A> {
         CellSink { impl_: CellSinkImpl::new(&sodium_ctx.impl_, a) }
     }
 
+    // The coalescer combines the values of several sends in one transaction. Without it a second
+    // send in a transaction panics in debug builds and replaces the first in release builds.
+    pub fn new_with_coalescer<COALESCER:FnMut(&A,&A)->A+Send+'static>(sodium_ctx: &SodiumCtx, a: A, coalescer: COALESCER) -> CellSink<A> {
+        CellSink { impl_: CellSinkImpl::new_with_coalescer(&sodium_ctx.impl_, a, coalescer) }
+    }
+
+    // a cell sink whose value is saved by SodiumCtx::checkpoint and seeded by SodiumCtx::restore under name
+    pub fn new_persistent<ENCODE,DECODE>(sodium_ctx: &SodiumCtx, name: &str, a: A, encode: ENCODE, decode: DECODE) -> Result<CellSink<A>,SodiumError>
+        where ENCODE: Fn(&A)->Vec<u8> + Send + 'static,
+              DECODE: FnOnce(&[u8])->Result<A,SodiumError>
+    {
+        let a = sodium_ctx.impl_.restored_or(name, a, decode)?;
+        let cs = CellSink::new(sodium_ctx, a);
+        cs.impl_.cell().persist(name, encode);
+        Ok(cs)
+    }
+
     pub fn cell(&self) -> Cell<A> {
         Cell { impl_: self.impl_.cell() }
     }
@@ -26,4 +52,28 @@ impl<A:Clone+Send+'static> CellSink<A> {
     pub fn send(&self, a: A) {
         self.impl_.send(a);
     }
+
+    pub fn try_send(&self, a: A) -> Result<(),SodiumError> {
+        self.impl_.try_send(a)
+    }
+
+    // see StreamSink::send_all
+    pub fn send_all<I:IntoIterator<Item=A>>(&self, as_: I) {
+        self.impl_.send_all(as_);
+    }
+
+    pub fn try_send_all<I:IntoIterator<Item=A>>(&self, as_: I) -> Result<(),SodiumError> {
+        self.impl_.try_send_all(as_)
+    }
+
+    // Sends f of the cell's value as of the current transaction, so it sees any earlier sends or
+    // modifies made in the same transaction. Several modifies in one transaction all take effect,
+    // but a send after a modify counts as a second send, see new_with_coalescer.
+    pub fn modify<F:FnOnce(&A)->A>(&self, f: F) {
+        self.impl_.modify(f);
+    }
+
+    pub fn try_modify<F:FnOnce(&A)->A>(&self, f: F) -> Result<(),SodiumError> {
+        self.impl_.try_modify(f)
+    }
 }
@@ -0,0 +1,344 @@
+use crate::Cell;
+use crate::IsLambda1;
+use crate::IsLambda2;
+use crate::SodiumCtx;
+use crate::Stream;
+use crate::StreamSink;
+use crate::impl_::lambda::{lambda1, lambda1_deps, lambda2, lambda2_deps};
+use crate::impl_::shared_tree::SharedTree;
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VecDiff<A> {
+    Insert { index: usize, value: A },
+    Remove { index: usize, value: A },
+    Update { index: usize, old: A, new: A }
+}
+
+impl<A:Clone> VecDiff<A> {
+    pub fn apply(&self, vec: &mut SharedVec<A>) {
+        match self {
+            VecDiff::Insert { index, value } => vec.insert(*index, value.clone()),
+            VecDiff::Remove { index, .. } => { vec.remove(*index); },
+            VecDiff::Update { index, new, .. } => vec.set(*index, new.clone())
+        }
+    }
+}
+
+// The value of a CellVec's cell. Clones share their storage and an edit only copies O(log n) of
+// it, so each transaction's vec costs O(log n) per diff however long the vec is.
+pub struct SharedVec<A> {
+    tree: SharedTree<A>
+}
+
+impl<A> Clone for SharedVec<A> {
+    fn clone(&self) -> Self {
+        SharedVec {
+            tree: self.tree.clone()
+        }
+    }
+}
+
+impl<A:Clone> SharedVec<A> {
+
+    pub fn new() -> SharedVec<A> {
+        SharedVec { tree: SharedTree::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&A> {
+        self.tree.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&A> {
+        self.tree.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<A> {
+        self.tree.iter().cloned().collect()
+    }
+
+    pub fn insert(&mut self, index: usize, value: A) {
+        self.tree.insert(index, value, 0);
+    }
+
+    pub fn push(&mut self, value: A) {
+        let len = self.tree.len();
+        self.tree.insert(len, value, 0);
+    }
+
+    pub fn remove(&mut self, index: usize) -> A {
+        self.tree.remove(index)
+    }
+
+    pub fn set(&mut self, index: usize, value: A) {
+        self.tree.set(index, value, 0);
+    }
+}
+
+impl<A:Clone> Default for SharedVec<A> {
+    fn default() -> Self {
+        SharedVec::new()
+    }
+}
+
+impl<A:Clone> From<Vec<A>> for SharedVec<A> {
+    fn from(vec: Vec<A>) -> Self {
+        SharedVec { tree: SharedTree::from_vec(vec.into_iter().map(|a| (a, 0)).collect()) }
+    }
+}
+
+impl<A:Clone> FromIterator<A> for SharedVec<A> {
+    fn from_iter<I:IntoIterator<Item=A>>(iter: I) -> Self {
+        SharedVec::from(iter.into_iter().collect::<Vec<A>>())
+    }
+}
+
+impl<A:Clone> Index<usize> for SharedVec<A> {
+    type Output = A;
+
+    fn index(&self, index: usize) -> &A {
+        match self.tree.get(index) {
+            Some(a) => a,
+            None => panic!("index {} is out of bounds for length {}", index, self.tree.len())
+        }
+    }
+}
+
+impl<A:Clone+PartialEq> PartialEq for SharedVec<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<A:Clone+fmt::Debug> fmt::Debug for SharedVec<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// A Vec held in a cell, along with a stream of the diffs that took it from one value to the next.
+// The diffs of one transaction arrive together, in the order they were made. Operators built on
+// a CellVec only look at the diffs, so a change to one element costs one call to the lambda no
+// matter how long the vec is.
+pub struct CellVec<A> {
+    sodium_ctx: SodiumCtx,
+    cell: Cell<SharedVec<A>>,
+    diffs: Stream<Vec<VecDiff<A>>>
+}
+
+impl<A> Clone for CellVec<A> {
+    fn clone(&self) -> Self {
+        CellVec {
+            sodium_ctx: SodiumCtx { impl_: self.sodium_ctx.impl_.clone() },
+            cell: self.cell.clone(),
+            diffs: self.diffs.clone()
+        }
+    }
+}
+
+impl<A:Clone+Send+Sync+'static> CellVec<A> {
+
+    // diffs must be valid against init, and against the result of each earlier firing
+    pub fn new(sodium_ctx: &SodiumCtx, init: SharedVec<A>, diffs: &Stream<Vec<VecDiff<A>>>) -> CellVec<A> {
+        let cell = diffs.accum(init, |diffs: &Vec<VecDiff<A>>, vec: &SharedVec<A>| {
+            let mut vec = vec.clone();
+            for diff in diffs {
+                diff.apply(&mut vec);
+            }
+            vec
+        });
+        CellVec {
+            sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+            cell,
+            diffs: diffs.clone()
+        }
+    }
+
+    pub fn cell(&self) -> Cell<SharedVec<A>> {
+        self.cell.clone()
+    }
+
+    pub fn diffs(&self) -> Stream<Vec<VecDiff<A>>> {
+        self.diffs.clone()
+    }
+
+    pub fn map<B:Clone+Send+Sync+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, mut f: FN) -> CellVec<B> {
+        self.sodium_ctx.transaction(|| {
+            let f_deps = lambda1_deps(&f);
+            let init: SharedVec<B> = self.cell.sample().iter().map(|a: &A| f.call(a)).collect();
+            // the mapped values are kept so removes and updates can report the old value without calling f again
+            let mapped = Arc::new(Mutex::new(init.clone()));
+            let diffs = self.diffs.map(lambda1(move |diffs: &Vec<VecDiff<A>>| {
+                let mut l = mapped.lock();
+                let mapped: &mut SharedVec<B> = l.as_mut().unwrap();
+                let mut result = Vec::with_capacity(diffs.len());
+                for diff in diffs {
+                    let diff2 = match diff {
+                        VecDiff::Insert { index, value } => VecDiff::Insert { index: *index, value: f.call(value) },
+                        VecDiff::Remove { index, .. } => VecDiff::Remove { index: *index, value: mapped[*index].clone() },
+                        VecDiff::Update { index, new, .. } => VecDiff::Update { index: *index, old: mapped[*index].clone(), new: f.call(new) }
+                    };
+                    diff2.apply(mapped);
+                    result.push(diff2);
+                }
+                result
+            }, f_deps));
+            CellVec::new(&self.sodium_ctx, init, &diffs)
+        })
+    }
+
+    pub fn filter<PRED:IsLambda1<A,bool>+Send+Sync+'static>(&self, mut pred: PRED) -> CellVec<A> {
+        self.sodium_ctx.transaction(|| {
+            let pred_deps = lambda1_deps(&pred);
+            let vec = self.cell.sample();
+            // one entry per element of the source vec, weighted 1 if it made it through, so an
+            // element's index in the output is the weight before it
+            let kept: Vec<((),usize)> = vec.iter().map(|a: &A| ((), pred.call(a) as usize)).collect();
+            let init: SharedVec<A> = vec.iter().zip(kept.iter()).filter(|(_, (_, keep))| *keep == 1).map(|(a, _)| a.clone()).collect();
+            let kept = Arc::new(Mutex::new(SharedTree::from_vec(kept)));
+            let diffs = self.diffs.map(lambda1(move |diffs: &Vec<VecDiff<A>>| {
+                let mut l = kept.lock();
+                let kept: &mut SharedTree<()> = l.as_mut().unwrap();
+                let mut result = Vec::new();
+                for diff in diffs {
+                    match diff {
+                        VecDiff::Insert { index, value } => {
+                            let keep = pred.call(value);
+                            if keep {
+                                result.push(VecDiff::Insert { index: kept.weight_before(*index), value: value.clone() });
+                            }
+                            kept.insert(*index, (), keep as usize);
+                        },
+                        VecDiff::Remove { index, value } => {
+                            if kept.weight(*index) == 1 {
+                                result.push(VecDiff::Remove { index: kept.weight_before(*index), value: value.clone() });
+                            }
+                            kept.remove(*index);
+                        },
+                        VecDiff::Update { index, old, new } => {
+                            let was_kept = kept.weight(*index) == 1;
+                            let keep = pred.call(new);
+                            let index2 = kept.weight_before(*index);
+                            match (was_kept, keep) {
+                                (true, true) => result.push(VecDiff::Update { index: index2, old: old.clone(), new: new.clone() }),
+                                (true, false) => result.push(VecDiff::Remove { index: index2, value: old.clone() }),
+                                (false, true) => result.push(VecDiff::Insert { index: index2, value: new.clone() }),
+                                (false, false) => ()
+                            }
+                            kept.set(*index, (), keep as usize);
+                        }
+                    }
+                }
+                result
+            }, pred_deps)).filter(|diffs: &Vec<VecDiff<A>>| !diffs.is_empty());
+            CellVec::new(&self.sodium_ctx, init, &diffs)
+        })
+    }
+
+    // Folds the elements into a single value. insert adds an element's contribution and remove
+    // takes it away again, an update is a remove of the old value followed by an insert of the new.
+    pub fn fold<B,INSERT,REMOVE>(&self, init: B, mut insert: INSERT, mut remove: REMOVE) -> Cell<B>
+        where B: Clone + Send + 'static,
+              INSERT: IsLambda2<B,A,B> + Send + Sync + 'static,
+              REMOVE: IsLambda2<B,A,B> + Send + Sync + 'static
+    {
+        self.sodium_ctx.transaction(|| {
+            let mut deps = lambda2_deps(&insert);
+            deps.append(&mut lambda2_deps(&remove));
+            let init = self.cell.sample().iter().fold(init, |b: B, a: &A| insert.call(&b, a));
+            self.diffs.accum(init, lambda2(move |diffs: &Vec<VecDiff<A>>, b: &B| {
+                let mut b = b.clone();
+                for diff in diffs {
+                    b = match diff {
+                        VecDiff::Insert { value, .. } => insert.call(&b, value),
+                        VecDiff::Remove { value, .. } => remove.call(&b, value),
+                        VecDiff::Update { old, new, .. } => {
+                            let b = remove.call(&b, old);
+                            insert.call(&b, new)
+                        }
+                    };
+                }
+                b
+            }, deps))
+        })
+    }
+}
+
+// Edits a CellVec. The sink keeps its own copy of the vec, so several edits can be made in one
+// transaction and each one sees the ones before it.
+pub struct CellVecSink<A> {
+    sink: StreamSink<Vec<VecDiff<A>>>,
+    vec: Arc<Mutex<SharedVec<A>>>,
+    cell_vec: CellVec<A>
+}
+
+impl<A> Clone for CellVecSink<A> {
+    fn clone(&self) -> Self {
+        CellVecSink {
+            sink: self.sink.clone(),
+            vec: self.vec.clone(),
+            cell_vec: self.cell_vec.clone()
+        }
+    }
+}
+
+impl<A:Clone+Send+Sync+'static> CellVecSink<A> {
+
+    pub fn new(sodium_ctx: &SodiumCtx, init: SharedVec<A>) -> CellVecSink<A> {
+        let sink = StreamSink::new_with_coalescer(sodium_ctx, |diffs1: &Vec<VecDiff<A>>, diffs2: &Vec<VecDiff<A>>| {
+            let mut diffs = diffs1.clone();
+            diffs.extend(diffs2.iter().cloned());
+            diffs
+        });
+        let cell_vec = CellVec::new(sodium_ctx, init.clone(), &sink.stream());
+        CellVecSink {
+            sink,
+            vec: Arc::new(Mutex::new(init)),
+            cell_vec
+        }
+    }
+
+    pub fn cell_vec(&self) -> CellVec<A> {
+        self.cell_vec.clone()
+    }
+
+    pub fn insert(&self, index: usize, value: A) {
+        self.send(|_: &SharedVec<A>| VecDiff::Insert { index, value });
+    }
+
+    pub fn push(&self, value: A) {
+        self.send(|vec: &SharedVec<A>| VecDiff::Insert { index: vec.len(), value });
+    }
+
+    pub fn remove(&self, index: usize) {
+        self.send(|vec: &SharedVec<A>| VecDiff::Remove { index, value: vec[index].clone() });
+    }
+
+    pub fn update(&self, index: usize, value: A) {
+        self.send(|vec: &SharedVec<A>| VecDiff::Update { index, old: vec[index].clone(), new: value });
+    }
+
+    fn send<K:FnOnce(&SharedVec<A>)->VecDiff<A>>(&self, k: K) {
+        let diff;
+        {
+            let mut l = self.vec.lock();
+            let vec: &mut SharedVec<A> = l.as_mut().unwrap();
+            diff = k(vec);
+            diff.apply(vec);
+        }
+        self.sink.send(vec![diff]);
+    }
+}
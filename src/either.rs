@@ -0,0 +1,22 @@
+// One of two values of different types, see Stream::merge_either.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Either<A,B> {
+    Left(A),
+    Right(B)
+}
+
+impl<A,B> Either<A,B> {
+    pub fn left(&self) -> Option<&A> {
+        match self {
+            Either::Left(a) => Some(a),
+            Either::Right(_) => None
+        }
+    }
+
+    pub fn right(&self) -> Option<&B> {
+        match self {
+            Either::Left(_) => None,
+            Either::Right(b) => Some(b)
+        }
+    }
+}
@@ -2,24 +2,29 @@ use crate::impl_::dep::Dep;
 use crate::impl_::lazy::Lazy;
 use crate::impl_::listener::Listener;
 use crate::impl_::node::{Node, WeakNode, IsNode};
+use crate::impl_::poison_policy;
 use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::sodium_ctx::SodiumCtxData;
+use crate::impl_::sodium_error::SodiumError;
 use crate::impl_::stream::Stream;
 use crate::impl_::stream::WeakStream;
+use crate::impl_::stream::StreamData;
 use crate::impl_::stream::StreamWeakForwardRef;
-use crate::impl_::lambda::IsLambda1;
-use crate::impl_::lambda::IsLambda2;
-use crate::impl_::lambda::IsLambda3;
-use crate::impl_::lambda::IsLambda4;
-use crate::impl_::lambda::IsLambda5;
-use crate::impl_::lambda::IsLambda6;
-use crate::impl_::lambda::{lambda1, lambda2, lambda3, lambda2_deps, lambda3_deps, lambda4_deps, lambda5_deps, lambda6_deps};
-
+use crate::impl_::lambda::{IsLambda1, IsLambda2, IsLambda3, IsLambda4, IsLambda5, IsLambda6};
+use crate::impl_::lambda::{IsLambda7, IsLambda8, IsLambda9, IsLambda10, IsLambda11, IsLambda12};
+use crate::impl_::lambda::{lambda1, lambda1_deps, lambda2_deps, lambda3_deps, lambda4_deps, lambda5_deps, lambda6_deps};
+use crate::impl_::lambda::{lambda7_deps, lambda8_deps, lambda9_deps, lambda10_deps, lambda11_deps, lambda12_deps};
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::future::Future;
+use std::hash::Hash;
 use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
 use std::sync::Weak;
+use std::time::Duration;
 
 pub struct CellWeakForwardRef<A> {
     data: Arc<RwLock<Option<WeakCell<A>>>>
@@ -51,6 +56,82 @@ impl<A:Send+'static> CellWeakForwardRef<A> {
     }
 }
 
+// Generates liftN. The latest value of each input cell is kept in state, and f runs once per
+// transaction in which any of the inputs changed.
+macro_rules! lift {
+    ($lift:ident, $is_lambda:ident, $lambda_deps:ident, [$($T:ident $c:ident $idx:tt),*], $R:ident) => {
+        #[allow(clippy::too_many_arguments)]
+        pub fn $lift<
+            $($T:Send+Clone+'static,)*
+            $R:Send+Clone+'static,
+            FN:$is_lambda<A,$($T,)* $R>+Send+'static
+        >(&self, $($c: &Cell<$T>,)* f: FN) -> Cell<$R> where A: Clone {
+            let sodium_ctx = self.sodium_ctx();
+            let init_state = (self.sample_lazy(), $($c.sample_lazy(),)*);
+            let init: Lazy<$R>;
+            let f_deps = $lambda_deps(&f);
+            let f = Arc::new(Mutex::new(f));
+            {
+                let init_state = init_state.clone();
+                let f = f.clone();
+                init = Lazy::new(move || {
+                    let mut l = f.lock();
+                    let f = l.as_mut().unwrap();
+                    f.call(&init_state.0.run(), $(&init_state.$idx.run()),*)
+                });
+            }
+            let state = Arc::new(Mutex::new(init_state));
+            let mut ss: Vec<Stream<()>> = Vec::new();
+            {
+                let state = state.clone();
+                ss.push(self.updates().map(move |a: &A| {
+                    let mut l = state.lock();
+                    let state2 = l.as_mut().unwrap();
+                    state2.0 = Lazy::of_value(a.clone());
+                }));
+            }
+            $(
+                {
+                    let state = state.clone();
+                    ss.push($c.updates().map(move |x: &$T| {
+                        let mut l = state.lock();
+                        let state2 = l.as_mut().unwrap();
+                        state2.$idx = Lazy::of_value(x.clone());
+                    }));
+                }
+            )*
+            let s = or_else_all(ss).map(lambda1(move |_: &()| {
+                let l = state.lock();
+                let state2 = l.as_ref().unwrap();
+                let mut l = f.lock();
+                let f = l.as_mut().unwrap();
+                f.call(&state2.0.run(), $(&state2.$idx.run()),*)
+            }, f_deps));
+            Cell::_new(
+                &sodium_ctx,
+                s,
+                init
+            )
+        }
+    };
+}
+
+// Merges as a balanced tree, so the depth of the merge grows with the log of the number of streams.
+fn or_else_all(mut ss: Vec<Stream<()>>) -> Stream<()> {
+    while ss.len() > 1 {
+        let mut ss2 = Vec::new();
+        let mut it = ss.into_iter();
+        while let Some(s1) = it.next() {
+            match it.next() {
+                Some(s2) => ss2.push(s1.or_else(&s2)),
+                None => ss2.push(s1)
+            }
+        }
+        ss = ss2;
+    }
+    ss.pop().unwrap()
+}
+
 pub struct Cell<A> {
     pub data: Arc<Mutex<CellData<A>>>,
     pub node: Node
@@ -82,13 +163,14 @@ impl<A> Clone for WeakCell<A> {
 pub struct CellData<A> {
     stream: Stream<A>,
     value: Lazy<A>,
-    next_value_op: Option<A>
+    // with the id of the transaction it was set in, a value left behind by an aborted transaction is never committed
+    next_value_op: Option<(u64,A)>
 }
 
 impl<A> Cell<A> {
     pub fn with_data<R,K:FnOnce(&mut CellData<A>)->R>(&self, k: K) -> R {
-        let mut l = self.data.lock();
-        let data: &mut CellData<A> = l.as_mut().unwrap();
+        let mut l = poison_policy::lock(&self.data, |_| self.node.sodium_ctx.poison_policy());
+        let data: &mut CellData<A> = &mut l;
         k(data)
     }
 
@@ -153,19 +235,20 @@ impl<A:Send+'static> Cell<A> {
                     let c = c.unwrap();
                     let firing_op = stream.with_firing_op(|firing_op| firing_op.clone());
                     if let Some(firing) = firing_op {
+                        let transaction_id = sodium_ctx.transaction_id();
                         let is_first =
                             c.with_data(|data: &mut CellData<A>| {
-                                let is_first = data.next_value_op.is_none();
-                                data.next_value_op = Some(firing);
+                                let is_first = !matches!(data.next_value_op, Some((id, _)) if id == transaction_id);
+                                data.next_value_op = Some((transaction_id, firing));
                                 is_first
                             });
                         if is_first {
                             let c = c.clone();
                             sodium_ctx.post(move || {
                                 c.with_data(|data: &mut CellData<A>| {
-                                    let mut next_value_op: Option<A> = None;
+                                    let mut next_value_op: Option<(u64,A)> = None;
                                     mem::swap(&mut next_value_op, &mut data.next_value_op);
-                                    if let Some(next_value) = next_value_op {
+                                    if let Some((_, next_value)) = next_value_op {
                                         data.value = Lazy::of_value(next_value);
                                     }
                                 })
@@ -186,8 +269,21 @@ impl<A:Send+'static> Cell<A> {
         c
     }
 
+    // the lazy value is run outside the cell's lock, as its thunk may sample other cells that lead back to this one
     pub fn sample(&self) -> A where A: Clone {
-        self.with_data(|data: &mut CellData<A>| data.value.run())
+        let poison_policy = self.node.sodium_ctx.poison_policy();
+        self.sample_lazy().run_with_poison_policy(poison_policy)
+    }
+
+    pub fn try_sample(&self) -> Result<A,SodiumError> where A: Clone {
+        let poison_policy = self.node.sodium_ctx.poison_policy();
+        self.sample_lazy().try_run_with_poison_policy(poison_policy)
+    }
+
+    // makes the value of this cell part of every SodiumCtx::checkpoint under the given name
+    pub fn persist<ENCODE:Fn(&A)->Vec<u8>+Send+'static>(&self, name: &str, encode: ENCODE) where A: Clone {
+        let c = Cell::downgrade(self);
+        self.sodium_ctx().add_checkpointed(name.to_string(), move || c.upgrade().map(|c: Cell<A>| encode(&c.sample())));
     }
 
     pub fn sample_lazy(&self) -> Lazy<A> {
@@ -198,6 +294,13 @@ impl<A:Send+'static> Cell<A> {
         self.with_data(|data: &mut CellData<A>| data.stream.clone())
     }
 
+    // labels the node of the stream feeding the cell too, as that is where a map's closure runs
+    pub fn named(&self, label: &str) -> Cell<A> {
+        self.node.set_label(label);
+        self.with_data(|data: &mut CellData<A>| data.stream.node().set_label(label));
+        self.clone()
+    }
+
     pub fn value(&self) -> Stream<A> where A: Clone {
         let sodium_ctx = self.sodium_ctx();
         sodium_ctx.transaction(|| {
@@ -208,7 +311,7 @@ impl<A:Send+'static> Cell<A> {
                 let spark = spark.clone();
                 let self_ = self.clone();
                 sodium_ctx.post(move || {
-                    let a = self_.with_data(|data: &mut CellData<A>| data.value.run());
+                    let a = self_.sample_lazy().run();
                     sodium_ctx2.transaction(|| {
                         let node = spark.node();
                         {
@@ -224,144 +327,72 @@ impl<A:Send+'static> Cell<A> {
         })
     }
 
-    pub fn map<B:Send+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, mut f: FN) -> Cell<B> where A: Clone, B: Clone {
-        let init = f.call(&self.sample());
-        self.updates().map(f).hold(init)
-    }
-
-    pub fn lift2<B:Send+Clone+'static,C:Send+Clone+'static,FN:IsLambda2<A,B,C>+Send+'static>(&self, cb: &Cell<B>, f: FN) -> Cell<C> where A: Clone {
+    // Fires the value every period, starting one period from now. The ticks are sent from timers,
+    // see SodiumCtx::run_timers, each in a transaction of its own.
+    pub fn sample_every(&self, period: Duration) -> Stream<A> where A: Clone {
         let sodium_ctx = self.sodium_ctx();
-        let lhs = self.sample_lazy();
-        let rhs = cb.sample_lazy();
-        let init: Lazy<C>;
-        let f_deps = lambda2_deps(&f);
-        let f = Arc::new(Mutex::new(f));
-        {
-            let lhs = lhs.clone();
-            let rhs = rhs.clone();
-            let f = f.clone();
-            init = Lazy::new(move || {
-                let mut l = f.lock();
-                let f = l.as_mut().unwrap();
-                f.call(&lhs.run(), &rhs.run())
-            });
-        }
-        let state: Arc<Mutex<(Lazy<A>,Lazy<B>)>> = Arc::new(Mutex::new((lhs, rhs)));
-        let s1: Stream<()>;
-        let s2: Stream<()>;
-        {
-            let state = state.clone();
-            s1 = self.updates().map(move |a: &A| {
-                let mut l = state.lock();
-                let state2: &mut (Lazy<A>,Lazy<B>) = l.as_mut().unwrap();
-                state2.0 = Lazy::of_value(a.clone());
-            });
-        }
-        {
-            let state = state.clone();
-            s2 = cb.updates().map(move |b: &B| {
-                let mut l = state.lock();
-                let state2: &mut (Lazy<A>,Lazy<B>) = l.as_mut().unwrap();
-                state2.1 = Lazy::of_value(b.clone());
-            });
-        }
-        let s = s1.or_else(&s2).map(lambda1(move |_: &()| {
-            let l = state.lock();
-            let state2: &(Lazy<A>,Lazy<B>) = l.as_ref().unwrap();
-            let mut l = f.lock();
-            let f = l.as_mut().unwrap();
-            f.call(&state2.0.run(), &state2.1.run())
-        }, f_deps));
-        Cell::_new(
-            &sodium_ctx,
-            s,
-            init
-        )
+        sodium_ctx.transaction(|| {
+            Stream::periodic(&sodium_ctx, period).snapshot1(self)
+        })
     }
 
-    pub fn lift3<
-        B:Send+Clone+'static,
-        C:Send+Clone+'static,
-        D:Send+Clone+'static,
-        FN:IsLambda3<A,B,C,D>+Send+'static
-    >(&self, cb: &Cell<B>, cc: &Cell<C>, mut f: FN) -> Cell<D> where A: Clone {
-        let f_deps = lambda3_deps(&f);
-        self
-            .lift2(
-                cb,
-                |a: &A, b: &B| (a.clone(), b.clone())
-            )
-            .lift2(
-                cc,
-                lambda2(move |(ref a, ref b): &(A,B), c: &C| f.call(a, b, c), f_deps)
-            )
+    // None until the future for the current value has finished, see Stream::map_async
+    pub fn map_async<B,FUT,FN>(&self, f: FN) -> Cell<Option<B>>
+        where A: Clone,
+              B: Send + Clone + 'static,
+              FUT: Future<Output=B> + Send + 'static,
+              FN: IsLambda1<A,FUT> + Send + Sync + 'static
+    {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let inputs = self.value();
+            inputs.map_async(f)
+                .map(|b: &B| Some(b.clone()))
+                .or_else(&inputs.map(|_: &A| None))
+                .hold(None)
+        })
     }
 
-    pub fn lift4<
-        B:Send+Clone+'static,
-        C:Send+Clone+'static,
-        D:Send+Clone+'static,
-        E:Send+Clone+'static,
-        FN:IsLambda4<A,B,C,D,E>+Send+'static
-    >(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, mut f: FN) -> Cell<E> where A: Clone {
-        let f_deps = lambda4_deps(&f);
-        self
-            .lift3(
-                cb,
-                cc,
-                |a: &A, b: &B, c: &C| (a.clone(), b.clone(), c.clone())
-            )
-            .lift2(
-                cd,
-                lambda2(move |(ref a, ref b, ref c): &(A,B,C), d: &D| f.call(a, b, c, d), f_deps)
-            )
+    pub fn map<B:Send+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, mut f: FN) -> Cell<B> where A: Clone, B: Clone {
+        let init = f.call(&self.sample());
+        self.updates().map(f).hold(init)
     }
 
-    pub fn lift5<
-        B:Send+Clone+'static,
-        C:Send+Clone+'static,
-        D:Send+Clone+'static,
-        E:Send+Clone+'static,
-        F:Send+Clone+'static,
-        FN:IsLambda5<A,B,C,D,E,F>+Send+'static
-    >(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, ce: &Cell<E>, mut f: FN) -> Cell<F> where A: Clone {
-        let f_deps = lambda5_deps(&f);
-        self
-            .lift3(
-                cb,
-                cc,
-                |a: &A, b: &B, c: &C| (a.clone(), b.clone(), c.clone())
-            )
-            .lift3(
-                cd,
-                ce,
-                lambda3(move |(ref a, ref b, ref c): &(A,B,C), d: &D, e: &E| f.call(a, b, c, d, e), f_deps)
-            )
+    pub fn map_lazy<B:Send+Clone+'static,FN:IsLambda1<A,B>+Send+Sync+'static>(&self, f: FN) -> Cell<B> where A: Clone {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let f_deps = lambda1_deps(&f);
+            let f = Arc::new(Mutex::new(f));
+            let init;
+            {
+                let f = f.clone();
+                init = self.sample_lazy().map(move |a: &A| {
+                    let mut l = f.lock();
+                    let f = l.as_mut().unwrap();
+                    f.call(a)
+                });
+            }
+            self.updates()
+                .map(lambda1(move |a: &A| {
+                    let mut l = f.lock();
+                    let f = l.as_mut().unwrap();
+                    f.call(a)
+                }, f_deps))
+                .hold_lazy(init)
+        })
     }
 
-    pub fn lift6<
-        B:Send+Clone+'static,
-        C:Send+Clone+'static,
-        D:Send+Clone+'static,
-        E:Send+Clone+'static,
-        F:Send+Clone+'static,
-        G:Send+Clone+'static,
-        FN:IsLambda6<A,B,C,D,E,F,G>+Send+'static
-    >(&self, cb: &Cell<B>, cc: &Cell<C>, cd: &Cell<D>, ce: &Cell<E>, cf: &Cell<F>, mut f: FN) -> Cell<G> where A: Clone {
-        let f_deps = lambda6_deps(&f);
-        self
-            .lift4(
-                cb,
-                cc,
-                cd,
-                |a: &A, b: &B, c: &C, d: &D| (a.clone(), b.clone(), c.clone(), d.clone())
-            )
-            .lift3(
-                ce,
-                cf,
-                lambda3(move |(ref a, ref b, ref c, ref d): &(A,B,C,D), e: &E, f2: &F| f.call(a, b, c, d, e, f2), f_deps)
-            )
-    }
+    lift!(lift2, IsLambda2, lambda2_deps, [B cb 1], C);
+    lift!(lift3, IsLambda3, lambda3_deps, [B cb 1, C cc 2], D);
+    lift!(lift4, IsLambda4, lambda4_deps, [B cb 1, C cc 2, D cd 3], E);
+    lift!(lift5, IsLambda5, lambda5_deps, [B cb 1, C cc 2, D cd 3, E ce 4], F);
+    lift!(lift6, IsLambda6, lambda6_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5], G);
+    lift!(lift7, IsLambda7, lambda7_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6], H);
+    lift!(lift8, IsLambda8, lambda8_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6, H ch 7], I);
+    lift!(lift9, IsLambda9, lambda9_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6, H ch 7, I ci 8], J);
+    lift!(lift10, IsLambda10, lambda10_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6, H ch 7, I ci 8, J cj 9], K);
+    lift!(lift11, IsLambda11, lambda11_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6, H ch 7, I ci 8, J cj 9, K ck 10], L);
+    lift!(lift12, IsLambda12, lambda12_deps, [B cb 1, C cc 2, D cd 3, E ce 4, F cf 5, G cg 6, H ch 7, I ci 8, J cj 9, K ck 10, L cl 11], M);
 
     pub fn switch_s(csa: &Cell<Stream<A>>) -> Stream<A> where A: Clone {
         let csa = csa.clone();
@@ -521,6 +552,163 @@ impl<A:Send+'static> Cell<A> {
         .hold_lazy(Lazy::new(move || cca2.sample().sample()))
     }
 
+    // Like switch_s, but over many keyed streams at once. Fires the values of all of the inner
+    // streams that fired in a transaction, by key. When the map changes only the streams that were
+    // added or removed have their dependencies changed.
+    pub fn switch_s_keyed<K:Eq+Hash+Clone+Send+'static>(csa: &Cell<HashMap<K,Stream<A>>>) -> Stream<HashMap<K,A>> where A: Clone {
+        let csa = csa.clone();
+        let sodium_ctx = csa.sodium_ctx();
+        Stream::_new(
+            &sodium_ctx,
+            |sa: StreamWeakForwardRef<HashMap<K,A>>| {
+                let init = csa.sample();
+                let inner: Arc<Mutex<HashMap<K,WeakStream<A>>>> = Arc::new(Mutex::new(
+                    init.iter().map(|(k, s): (&K, &Stream<A>)| (k.clone(), Stream::downgrade(s))).collect()
+                ));
+                let csa_updates = csa.updates();
+                let csa_updates_dep = csa_updates.to_dep();
+                let mut dependencies = distinct_stream_nodes(init.values());
+                dependencies.push(csa_updates.box_clone());
+                let node = Node::new(
+                    &sodium_ctx,
+                    "switch_s_keyed node",
+                    || {},
+                    dependencies
+                );
+                let node_update;
+                {
+                    let sodium_ctx = sodium_ctx.clone();
+                    let node = Node::downgrade2(&node);
+                    node_update = move || {
+                        let mut out: HashMap<K,A> = HashMap::new();
+                        {
+                            let l = inner.lock();
+                            let inner: &HashMap<K,WeakStream<A>> = l.as_ref().unwrap();
+                            for (k, s) in inner {
+                                if let Some(s) = s.upgrade() {
+                                    s.with_firing_op(|firing_op: &mut Option<A>| {
+                                        if let Some(ref firing) = firing_op {
+                                            out.insert(k.clone(), firing.clone());
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                        csa_updates.with_firing_op(|firing_op: &mut Option<HashMap<K,Stream<A>>>| {
+                            if let Some(ref firing) = firing_op {
+                                let firing = firing.clone();
+                                let node = node.clone();
+                                let inner = inner.clone();
+                                sodium_ctx.pre_post(move || {
+                                    let mut l = inner.lock();
+                                    let inner: &mut HashMap<K,WeakStream<A>> = l.as_mut().unwrap();
+                                    if let Some(node) = node.upgrade2() {
+                                        swap_inner_dependencies(&node, inner.values(), firing.values());
+                                    }
+                                    *inner = firing.iter().map(|(k, s): (&K, &Stream<A>)| (k.clone(), Stream::downgrade(s))).collect();
+                                });
+                            }
+                        });
+                        if !out.is_empty() {
+                            sa.unwrap()._send(out);
+                        }
+                    };
+                }
+                IsNode::add_update_dependencies(&node, vec![csa_updates_dep]);
+                {
+                    let mut update = node.data.update.write().unwrap();
+                    *update = Box::new(node_update);
+                }
+                node
+            }
+        )
+    }
+
+    // Like switch_c, but over a list of keyed cells at once, giving their values in list order.
+    // An inner cell updating only replaces its own entry, and when the list changes only the cells
+    // that were added or removed have their dependencies changed.
+    pub fn switch_c_keyed<K:Clone+Send+'static>(cca: &Cell<Vec<(K,Cell<A>)>>) -> Cell<Vec<(K,A)>> where A: Clone {
+        let cca = cca.clone();
+        let sodium_ctx = cca.sodium_ctx();
+        let init = cca.sample();
+        let init_values: Vec<(K,A)> = init.iter().map(|(k, c): &(K,Cell<A>)| (k.clone(), c.sample())).collect();
+        Stream::_new(
+            &sodium_ctx,
+            |sa: StreamWeakForwardRef<Vec<(K,A)>>| {
+                let inner_updates: Vec<Stream<A>> = init.iter().map(|(_, c): &(K,Cell<A>)| c.updates()).collect();
+                let inner: Arc<Mutex<Vec<WeakStream<A>>>> = Arc::new(Mutex::new(inner_updates.iter().map(Stream::downgrade).collect()));
+                let values: Arc<Mutex<Vec<(K,A)>>> = Arc::new(Mutex::new(init_values.clone()));
+                let cca_updates = cca.updates();
+                let cca_updates_dep = cca_updates.to_dep();
+                let mut dependencies = distinct_stream_nodes(inner_updates.iter());
+                dependencies.push(cca_updates.box_clone());
+                let node = Node::new(
+                    &sodium_ctx,
+                    "switch_c_keyed node",
+                    || {},
+                    dependencies
+                );
+                let node_update;
+                {
+                    let sodium_ctx = sodium_ctx.clone();
+                    let node = Node::downgrade2(&node);
+                    node_update = move || {
+                        let outer_firing_op: Option<Vec<(K,Cell<A>)>> = cca_updates.with_firing_op(|firing_op: &mut Option<Vec<(K,Cell<A>)>>| firing_op.clone());
+                        let mut l = values.lock();
+                        let values: &mut Vec<(K,A)> = l.as_mut().unwrap();
+                        let mut changed = false;
+                        if let Some(firing) = outer_firing_op {
+                            // inner cells updating in the same transaction as the list win over their old values
+                            *values =
+                                firing
+                                    .iter()
+                                    .map(|(k, c): &(K,Cell<A>)| {
+                                        let a = c.updates().with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                                        (k.clone(), a.unwrap_or_else(|| c.sample()))
+                                    })
+                                    .collect();
+                            changed = true;
+                            let node = node.clone();
+                            let inner = inner.clone();
+                            sodium_ctx.pre_post(move || {
+                                let new_inner: Vec<Stream<A>> = firing.iter().map(|(_, c): &(K,Cell<A>)| c.updates()).collect();
+                                let mut l = inner.lock();
+                                let inner: &mut Vec<WeakStream<A>> = l.as_mut().unwrap();
+                                if let Some(node) = node.upgrade2() {
+                                    swap_inner_dependencies(&node, inner.iter(), new_inner.iter());
+                                }
+                                *inner = new_inner.iter().map(Stream::downgrade).collect();
+                            });
+                        } else {
+                            let l = inner.lock();
+                            let inner: &Vec<WeakStream<A>> = l.as_ref().unwrap();
+                            for (i, s) in inner.iter().enumerate() {
+                                if let Some(s) = s.upgrade() {
+                                    s.with_firing_op(|firing_op: &mut Option<A>| {
+                                        if let Some(ref firing) = firing_op {
+                                            values[i].1 = firing.clone();
+                                            changed = true;
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                        if changed {
+                            sa.unwrap()._send(values.clone());
+                        }
+                    };
+                }
+                IsNode::add_update_dependencies(&node, vec![cca_updates_dep]);
+                {
+                    let mut update = node.data.update.write().unwrap();
+                    *update = Box::new(node_update);
+                }
+                node
+            }
+        )
+        .hold(init_values)
+    }
+
     pub fn listen_weak<K: FnMut(&A)+Send+Sync+'static>(&self, k: K) -> Listener where A: Clone {
         self.sodium_ctx().transaction(|| {
             self.value().listen_weak(k)
@@ -553,3 +741,39 @@ impl<A> WeakCell<A> {
         Some(Cell { data, node })
     }
 }
+
+// one node per distinct stream, the same stream may be used under more than one key
+fn distinct_stream_nodes<'a,A:Send+'static,I:Iterator<Item=&'a Stream<A>>>(streams: I) -> Vec<Box<dyn IsNode+Send+Sync>> {
+    let mut seen: HashSet<*const Mutex<StreamData<A>>> = HashSet::new();
+    streams
+        .filter(|s: &&Stream<A>| seen.insert(Arc::as_ptr(&s.data)))
+        .map(|s: &Stream<A>| s.box_clone())
+        .collect()
+}
+
+// makes node depend on the new inner streams instead of the old ones, leaving streams that are in both alone
+fn swap_inner_dependencies<'a,A,OLD,NEW>(node: &Node, old: OLD, new: NEW)
+    where A: Send + 'static,
+          OLD: Iterator<Item=&'a WeakStream<A>>,
+          NEW: Iterator<Item=&'a Stream<A>>
+{
+    let old: HashMap<*const Mutex<StreamData<A>>,Stream<A>> =
+        old
+            .flat_map(|s: &WeakStream<A>| s.upgrade())
+            .map(|s: Stream<A>| (Arc::as_ptr(&s.data), s))
+            .collect();
+    let new: HashMap<*const Mutex<StreamData<A>>,&Stream<A>> =
+        new
+            .map(|s: &Stream<A>| (Arc::as_ptr(&s.data), s))
+            .collect();
+    for (ptr, s) in &old {
+        if !new.contains_key(ptr) {
+            IsNode::remove_dependency(node, s);
+        }
+    }
+    for (ptr, s) in &new {
+        if !old.contains_key(ptr) {
+            IsNode::add_dependency(node, (*s).clone());
+        }
+    }
+}
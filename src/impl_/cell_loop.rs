@@ -1,14 +1,14 @@
 use crate::impl_::cell::Cell;
 use crate::impl_::lazy::Lazy;
 use crate::impl_::sodium_ctx::SodiumCtx;
+use crate::impl_::sodium_error::SodiumError;
 use crate::impl_::stream_loop::StreamLoop;
 
-use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 pub struct CellLoop<A> {
-    pub init_value_op: Arc<Mutex<Option<A>>>,
+    pub init_value_op: Arc<Mutex<Option<Lazy<A>>>>,
     pub stream_loop: StreamLoop<A>,
     pub cell: Cell<A>,
 }
@@ -26,19 +26,31 @@ impl<A> Clone for CellLoop<A> {
 impl<A:Send+Clone+'static> CellLoop<A> {
 
     pub fn new(sodium_ctx: &SodiumCtx) -> CellLoop<A> {
-        let init_value_op: Arc<Mutex<Option<A>>> = Arc::new(Mutex::new(None));
+        let init_value_op: Arc<Mutex<Option<Lazy<A>>>> = Arc::new(Mutex::new(None));
         let init_value: Lazy<A>;
         {
             let init_value_op = init_value_op.clone();
-            init_value = Lazy::new(move || {
-                let mut l = init_value_op.lock();
-                let init_value_op: &mut Option<A> = l.as_mut().unwrap();
-                let mut result_op: Option<A> = None;
-                mem::swap(&mut result_op, init_value_op);
-                if let Some(init_value) = result_op {
-                    return init_value.clone();
+            init_value = Lazy::new_fallible(move || {
+                // taken out so the lock is not held while the looped cell's value is worked out,
+                // and only put back if that fails
+                let init_value_op2: Option<Lazy<A>>;
+                {
+                    let mut l = init_value_op.lock();
+                    let init_value_op: &mut Option<Lazy<A>> = l.as_mut().unwrap();
+                    init_value_op2 = init_value_op.take();
+                }
+                match init_value_op2 {
+                    Some(init_value) => {
+                        let result = init_value.try_run();
+                        if result.is_err() {
+                            let mut l = init_value_op.lock();
+                            let init_value_op: &mut Option<Lazy<A>> = l.as_mut().unwrap();
+                            *init_value_op = Some(init_value);
+                        }
+                        result
+                    },
+                    None => Err(SodiumError::CellLoopSampledBeforeLooped)
                 }
-                panic!("CellLoop sampled before looped.");
             });
         }
         let stream_loop = StreamLoop::new(sodium_ctx);
@@ -55,9 +67,16 @@ impl<A:Send+Clone+'static> CellLoop<A> {
     }
 
     pub fn loop_(&self, ca: &Cell<A>) {
-        self.stream_loop.loop_(&ca.updates());
+        if let Err(err) = self.try_loop(ca) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_loop(&self, ca: &Cell<A>) -> Result<(),SodiumError> {
+        self.stream_loop.try_loop(&ca.updates())?;
         let mut l = self.init_value_op.lock();
-        let init_value_op: &mut Option<A> = l.as_mut().unwrap();
-        *init_value_op = Some(ca.sample());
+        let init_value_op: &mut Option<Lazy<A>> = l.as_mut().unwrap();
+        *init_value_op = Some(ca.sample_lazy());
+        Ok(())
     }
 }
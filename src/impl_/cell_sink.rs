@@ -1,5 +1,7 @@
 use crate::impl_::cell::Cell;
 use crate::impl_::sodium_ctx::SodiumCtx;
+use crate::impl_::sodium_error::SodiumError;
+use crate::impl_::stream::StreamData;
 use crate::impl_::stream_sink::StreamSink;
 
 pub struct CellSink<A> {
@@ -25,6 +27,14 @@ impl<A:Send+Clone+'static> CellSink<A> {
         }
     }
 
+    pub fn new_with_coalescer<COALESCER:FnMut(&A,&A)->A+Send+'static>(sodium_ctx: &SodiumCtx, a: A, coalescer: COALESCER) -> CellSink<A> {
+        let stream_sink = StreamSink::new_with_coalescer(sodium_ctx, coalescer);
+        CellSink {
+            cell: stream_sink.stream().hold(a),
+            stream_sink
+        }
+    }
+
     pub fn cell(&self) -> Cell<A> {
         self.cell.clone()
     }
@@ -32,4 +42,40 @@ impl<A:Send+Clone+'static> CellSink<A> {
     pub fn send(&self, a: A) {
         self.stream_sink.send(a);
     }
+
+    pub fn try_send(&self, a: A) -> Result<(),SodiumError> {
+        self.stream_sink.try_send(a)
+    }
+
+    pub fn send_all<I:IntoIterator<Item=A>>(&self, as_: I) {
+        self.stream_sink.send_all(as_);
+    }
+
+    pub fn try_send_all<I:IntoIterator<Item=A>>(&self, as_: I) -> Result<(),SodiumError> {
+        self.stream_sink.try_send_all(as_)
+    }
+
+    pub fn modify<F:FnOnce(&A)->A>(&self, f: F) {
+        if let Err(err) = self.try_modify(f) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_modify<F:FnOnce(&A)->A>(&self, f: F) -> Result<(),SodiumError> {
+        let sodium_ctx = self.cell.sodium_ctx();
+        sodium_ctx.try_transaction(|| {
+            // an earlier send in this transaction is replaced rather than coalesced with, the new value already builds on it
+            let mut f_op = Some(f);
+            let stream = self.stream_sink.stream();
+            stream.with_data(|data: &mut StreamData<A>| {
+                if let Some(ref mut firing) = data.firing_op {
+                    *firing = (f_op.take().unwrap())(firing);
+                }
+            });
+            match f_op {
+                Some(f) => self.stream_sink.try_send(f(&self.cell.sample())),
+                None => Ok(())
+            }
+        }).and_then(|result| result)
+    }
 }
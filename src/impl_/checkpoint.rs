@@ -0,0 +1,93 @@
+use crate::impl_::sodium_error::SodiumError;
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"SODIUMCP1";
+
+// The encoded values of every persistent cell at the time SodiumCtx::checkpoint was called, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    values: BTreeMap<String,Vec<u8>>
+}
+
+impl Checkpoint {
+    pub fn new() -> Checkpoint {
+        Checkpoint { values: BTreeMap::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.values.get(name).map(|value: &Vec<u8>| value.as_slice())
+    }
+
+    pub fn insert(&mut self, name: String, value: Vec<u8>) {
+        self.values.insert(name, value);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.values.keys().map(|name: &String| name.as_str())
+    }
+
+    // magic, then the entry count, then each name and value prefixed by its length, all lengths little endian u32
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+        for (name, value) in &self.values {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Checkpoint,SodiumError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SodiumError::Checkpoint("not a checkpoint".to_string()));
+        }
+        let mut reader = Reader { bytes: &bytes[MAGIC.len()..] };
+        let count = reader.read_u32()?;
+        let mut checkpoint = Checkpoint::new();
+        for _ in 0..count {
+            let name_len = reader.read_u32()? as usize;
+            let name = String::from_utf8(reader.read(name_len)?.to_vec()).map_err(|_| SodiumError::Checkpoint("name is not utf-8".to_string()))?;
+            let value_len = reader.read_u32()? as usize;
+            let value = reader.read(value_len)?.to_vec();
+            checkpoint.insert(name, value);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(SodiumError::Checkpoint("trailing bytes".to_string()));
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn write_to_file<P:AsRef<Path>>(&self, path: P) -> Result<(),SodiumError> {
+        fs::write(path, self.to_bytes()).map_err(|err| SodiumError::Checkpoint(err.to_string()))
+    }
+
+    pub fn read_from_file<P:AsRef<Path>>(path: P) -> Result<Checkpoint,SodiumError> {
+        let bytes = fs::read(path).map_err(|err| SodiumError::Checkpoint(err.to_string()))?;
+        Checkpoint::from_bytes(&bytes)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8],SodiumError> {
+        if self.bytes.len() < len {
+            return Err(SodiumError::Checkpoint("truncated".to_string()));
+        }
+        let (result, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(result)
+    }
+
+    fn read_u32(&mut self) -> Result<u32,SodiumError> {
+        Ok(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+    }
+}
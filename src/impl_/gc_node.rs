@@ -1,3 +1,5 @@
+use crate::impl_::sodium_error::SodiumError;
+
 use std::cell::Cell;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -79,20 +81,30 @@ impl GcCtx {
     }
 
     pub fn collect_cycles(&self) {
+        if let Err(err) = self.try_collect_cycles() {
+            panic!("{}", err);
+        }
+    }
+
+    // An error from mark_roots leaves the graph as it was before the pass, so collecting again
+    // reports the same error. An error from collect_roots means a node that is still referenced
+    // has been freed, which can't be undone, and the context should not be used after it.
+    pub fn try_collect_cycles(&self) -> Result<(),SodiumError> {
         loop {
             trace!("start: collect_cycles");
-            self.mark_roots();
+            self.mark_roots()?;
             self.scan_roots();
-            self.collect_roots();
+            self.collect_roots()?;
             trace!("end: collect_cycles");
             let bail = self.with_data(|data: &mut GcCtxData| data.roots.is_empty() && data.to_be_freed.is_empty());
             if bail {
                 break;
             }
         }
+        Ok(())
     }
 
-    fn mark_roots(&self) {
+    fn mark_roots(&self) -> Result<(),SodiumError> {
         trace!("start: mark_roots");
         let mut old_roots: Vec<GcNode> = Vec::new();
         self.with_data(
@@ -104,10 +116,13 @@ impl GcCtx {
         for root in &old_roots {
             self.reset_ref_count_adj(root);
         }
+        let mut result = Ok(());
         for root in old_roots {
             let color = root.data.color.get();
             if color == Color::Purple {
-                self.mark_gray(&root);
+                if result.is_ok() {
+                    result = self.mark_gray(&root);
+                }
                 new_roots.push(root);
             } else {
                 root.data.buffered.set(false);
@@ -119,11 +134,27 @@ impl GcCtx {
                 }
             }
         }
+        if result.is_err() {
+            self.undo_mark_gray(&new_roots);
+        }
         self.with_data(
             |data: &mut GcCtxData|
                 std::mem::swap(&mut new_roots, &mut data.roots)
         );
         trace!("end: mark_roots");
+        result
+    }
+
+    // puts the nodes marked gray back to black and the roots back to purple, so that the roots
+    // are buffered again for the next pass
+    fn undo_mark_gray(&self, roots: &Vec<GcNode>) {
+        for root in roots {
+            self.scan_black(root);
+        }
+        for root in roots {
+            self.reset_ref_count_adj(root);
+            root.data.color.set(Color::Purple);
+        }
     }
 
     fn display_graph(&self, roots: &Vec<GcNode>) {
@@ -169,20 +200,26 @@ impl GcCtx {
         trace!("-- end of graph drawing --");
     }
 
-    fn mark_gray(&self, s: &GcNode) {
+    fn mark_gray(&self, s: &GcNode) -> Result<(),SodiumError> {
         if s.data.color.get() == Color::Gray {
-            return;
+            return Ok(());
         }
         s.data.color.set(Color::Gray);
 
+        let mut result = Ok(());
         s.trace(&mut |t: &GcNode| {
+            if result.is_err() {
+                return;
+            }
             trace!("mark_gray: gc node {} dec ref count", t.id);
             t.data.ref_count_adj.set(t.data.ref_count_adj.get() + 1);
             if t.data.ref_count_adj.get() > t.data.ref_count.get() {
-                panic!("ref count adj was larger than ref count for node {} ({}) (ref adj {}) (ref cnt {})", t.id, t.name, t.data.ref_count_adj.get(), t.data.ref_count.get());
+                result = Err(SodiumError::GcInvariant(format!("ref count adj was larger than ref count for node {} ({}) (ref adj {}) (ref cnt {})", t.id, t.name, t.data.ref_count_adj.get(), t.data.ref_count.get())));
+                return;
             }
-            self.mark_gray(t);
+            result = self.mark_gray(t);
         });
+        result
     }
 
     fn scan_roots(&self) {
@@ -243,7 +280,7 @@ impl GcCtx {
         });
     }
 
-    fn collect_roots(&self) {
+    fn collect_roots(&self) -> Result<(),SodiumError> {
         let mut white = Vec::new();
         let mut roots = Vec::new();
         self.with_data(|data: &mut GcCtxData| roots.append(&mut data.roots));
@@ -267,16 +304,12 @@ impl GcCtx {
                 self.with_data(|data: &mut GcCtxData| data.roots.retain(|root: &GcNode| root.id != i.id));
             }
         }
-        for i in white {
-            if i.ref_count() != 0 {
-                panic!("freed node ref count did not drop to zero for node {} ({})", i.id, i.name);
-            }
-        }
-        for i in to_be_freed {
+        for i in white.iter().chain(to_be_freed.iter()) {
             if i.ref_count() != 0 {
-                panic!("freed node ref count did not drop to zero for node {} ({})", i.id, i.name);
+                return Err(SodiumError::GcInvariant(format!("freed node ref count did not drop to zero for node {} ({})", i.id, i.name)));
             }
         }
+        Ok(())
     }
 
     fn collect_white(&self, s: &GcNode, white: &mut Vec<GcNode>) {
@@ -319,6 +352,18 @@ impl GcNode {
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ptr_eq(&self, other: &GcNode) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
     pub fn ref_count(&self) -> u32 {
         self.data.ref_count.get()
     }
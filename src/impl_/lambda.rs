@@ -5,212 +5,134 @@ pub struct Lambda<FN> {
     deps: Vec<Dep>
 }
 
-pub fn lambda1_deps<A,B,FN:IsLambda1<A,B>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub fn lambda2_deps<A,B,C,FN:IsLambda2<A,B,C>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub fn lambda3_deps<A,B,C,D,FN:IsLambda3<A,B,C,D>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub fn lambda4_deps<A,B,C,D,E,FN:IsLambda4<A,B,C,D,E>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub fn lambda5_deps<A,B,C,D,E,F,FN:IsLambda5<A,B,C,D,E,F>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub fn lambda6_deps<A,B,C,D,E,F,G,FN:IsLambda6<A,B,C,D,E,F,G>>(f: &FN) -> Vec<Dep> {
-    f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
-}
-
-pub trait IsLambda1<A,B> {
-    fn call(&mut self, a: &A) -> B;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-pub trait IsLambda2<A,B,C> {
-    fn call(&mut self, a: &A, b: &B) -> C;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-pub trait IsLambda3<A,B,C,D> {
-    fn call(&mut self, a: &A, b: &B, c: &C) -> D;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-pub trait IsLambda4<A,B,C,D,E> {
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D) -> E;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-pub trait IsLambda5<A,B,C,D,E,F> {
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E) -> F;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-pub trait IsLambda6<A,B,C,D,E,F,G> {
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E, f: &F) -> G;
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
-}
-
-impl<A,B,FN:FnMut(&A)->B> IsLambda1<A,B> for Lambda<FN> {
-
-    fn call(&mut self, a: &A) -> B {
-        (self.f)(a)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
+impl<FN> Lambda<FN> {
+    pub fn new(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
+        Lambda { f, deps }
     }
 }
 
-impl<A,B,FN:FnMut(&A)->B> IsLambda1<A,B> for FN {
-
-    fn call(&mut self, a: &A) -> B {
-        self(a)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
-    }
+// Anything that holds sodium objects and so must be declared as a dependency when captured by a lambda.
+pub trait Traceable {
+    fn deps(&self) -> Vec<Dep>;
 }
 
-impl<A,B,C,FN:FnMut(&A,&B)->C> IsLambda2<A,B,C> for Lambda<FN> {
-
-    fn call(&mut self, a: &A, b: &B) -> C {
-        (self.f)(a,b)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
+impl Traceable for Dep {
+    fn deps(&self) -> Vec<Dep> {
+        vec![self.clone()]
     }
 }
 
-impl<A,B,C,FN:FnMut(&A,&B)->C> IsLambda2<A,B,C> for FN {
-
-    fn call(&mut self, a: &A, b: &B) -> C {
-        self(a,b)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
+impl<T:Traceable+?Sized> Traceable for &T {
+    fn deps(&self) -> Vec<Dep> {
+        (**self).deps()
     }
 }
 
-impl<A,B,C,D,FN:FnMut(&A,&B,&C)->D> IsLambda3<A,B,C,D> for Lambda<FN> {
-
-    fn call(&mut self, a: &A, b: &B, c: &C) -> D {
-        (self.f)(a,b,c)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
+impl<T:Traceable> Traceable for Vec<T> {
+    fn deps(&self) -> Vec<Dep> {
+        self.iter().flat_map(|x: &T| x.deps()).collect()
     }
 }
 
-impl<A,B,C,D,FN:FnMut(&A,&B,&C)->D> IsLambda3<A,B,C,D> for FN {
-
-    fn call(&mut self, a: &A, b: &B, c: &C) -> D {
-        self(a,b,c)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
+impl<T:Traceable> Traceable for Option<T> {
+    fn deps(&self) -> Vec<Dep> {
+        self.iter().flat_map(|x: &T| x.deps()).collect()
     }
 }
 
-impl<A,B,C,D,E,FN:FnMut(&A,&B,&C,&D)->E> IsLambda4<A,B,C,D,E> for Lambda<FN> {
-
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D) -> E {
-        (self.f)(a,b,c,d)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
+impl<T1:Traceable,T2:Traceable> Traceable for (T1,T2) {
+    fn deps(&self) -> Vec<Dep> {
+        let mut deps = self.0.deps();
+        deps.append(&mut self.1.deps());
+        deps
     }
 }
 
-impl<A,B,C,D,E,FN:FnMut(&A,&B,&C,&D)->E> IsLambda4<A,B,C,D,E> for FN {
-
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D) -> E {
-        self(a,b,c,d)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
+impl<T1:Traceable,T2:Traceable,T3:Traceable> Traceable for (T1,T2,T3) {
+    fn deps(&self) -> Vec<Dep> {
+        let mut deps = self.0.deps();
+        deps.append(&mut self.1.deps());
+        deps.append(&mut self.2.deps());
+        deps
     }
 }
 
-impl<A,B,C,D,E,F,FN:FnMut(&A,&B,&C,&D,&E)->F> IsLambda5<A,B,C,D,E,F> for Lambda<FN> {
-
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E) -> F {
-        (self.f)(a,b,c,d,e)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
+impl<T1:Traceable,T2:Traceable,T3:Traceable,T4:Traceable> Traceable for (T1,T2,T3,T4) {
+    fn deps(&self) -> Vec<Dep> {
+        let mut deps = self.0.deps();
+        deps.append(&mut self.1.deps());
+        deps.append(&mut self.2.deps());
+        deps.append(&mut self.3.deps());
+        deps
     }
 }
 
-impl<A,B,C,D,E,F,FN:FnMut(&A,&B,&C,&D,&E)->F> IsLambda5<A,B,C,D,E,F> for FN {
-
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E) -> F {
-        self(a,b,c,d,e)
-    }
-
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
-    }
+// Builds a lambda whose dependencies are taken from the listed captures, e.g.
+// sodium_lambda!(move |a: &i32| *a + cx.sample(), [cx])
+// The dependencies are collected before the closure is built, so the captures can be moved into it.
+#[macro_export]
+macro_rules! sodium_lambda {
+    ($f:expr, [$($capture:expr),* $(,)?]) => {{
+        #[allow(unused_mut)]
+        let mut deps: Vec<$crate::Dep> = Vec::new();
+        $(
+            deps.append(&mut $crate::Traceable::deps(&$capture));
+        )*
+        $crate::Lambda::new($f, deps)
+    }};
 }
 
-impl<A,B,C,D,E,F,G,FN:FnMut(&A,&B,&C,&D,&E,&F)->G> IsLambda6<A,B,C,D,E,F,G> for Lambda<FN> {
+// Generates IsLambdaN, its impls for Lambda and plain closures, lambdaN and lambdaN_deps for one arity.
+macro_rules! is_lambda {
+    ($is_lambda:ident, $lambda:ident, $lambda_deps:ident, [$($T:ident $t:ident),*], $R:ident) => {
+        #[allow(clippy::too_many_arguments)]
+        pub trait $is_lambda<$($T,)* $R> {
+            fn call(&mut self, $($t: &$T),*) -> $R;
+            fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>>;
+        }
 
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E, f: &F) -> G {
-        (self.f)(a,b,c,d,e,f)
-    }
+        impl<$($T,)* $R, FN:FnMut($(&$T),*)->$R> $is_lambda<$($T,)* $R> for Lambda<FN> {
 
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        Some(&self.deps)
-    }
-}
+            fn call(&mut self, $($t: &$T),*) -> $R {
+                (self.f)($($t),*)
+            }
 
-impl<A,B,C,D,E,F,G,FN:FnMut(&A,&B,&C,&D,&E,&F)->G> IsLambda6<A,B,C,D,E,F,G> for FN {
+            fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
+                Some(&self.deps)
+            }
+        }
 
-    fn call(&mut self, a: &A, b: &B, c: &C, d: &D, e: &E, f: &F) -> G {
-        self(a,b,c,d,e,f)
-    }
+        impl<$($T,)* $R, FN:FnMut($(&$T),*)->$R> $is_lambda<$($T,)* $R> for FN {
 
-    fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
-        None
-    }
-}
-
-pub fn lambda1<A,B,FN:FnMut(&A)->B>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
-}
-
-pub fn lambda2<A,B,C,FN:FnMut(&A,&B)->C>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
-}
+            fn call(&mut self, $($t: &$T),*) -> $R {
+                self($($t),*)
+            }
 
-pub fn lambda3<A,B,C,D,FN:FnMut(&A,&B,&C)->D>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
-}
+            fn deps_op<'r>(&'r self) -> Option<&'r Vec<Dep>> {
+                None
+            }
+        }
 
-pub fn lambda4<A,B,C,D,E,FN:FnMut(&A,&B,&C,&D)->E>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
-}
+        #[allow(clippy::too_many_arguments)]
+        pub fn $lambda<$($T,)* $R, FN:FnMut($(&$T),*)->$R>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
+            Lambda { f, deps }
+        }
 
-pub fn lambda5<A,B,C,D,E,F,FN:FnMut(&A,&B,&C,&D,&E)->F>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
+        pub fn $lambda_deps<$($T,)* $R, FN:$is_lambda<$($T,)* $R>>(f: &FN) -> Vec<Dep> {
+            f.deps_op().map(|deps| deps.clone()).unwrap_or_else(|| Vec::new())
+        }
+    };
 }
 
-pub fn lambda6<A,B,C,D,E,F,G,FN:FnMut(&A,&B,&C,&D,&E,&F)->G>(f: FN, deps: Vec<Dep>) -> Lambda<FN> {
-    Lambda { f, deps }
-}
+is_lambda!(IsLambda1, lambda1, lambda1_deps, [A a], B);
+is_lambda!(IsLambda2, lambda2, lambda2_deps, [A a, B b], C);
+is_lambda!(IsLambda3, lambda3, lambda3_deps, [A a, B b, C c], D);
+is_lambda!(IsLambda4, lambda4, lambda4_deps, [A a, B b, C c, D d], E);
+is_lambda!(IsLambda5, lambda5, lambda5_deps, [A a, B b, C c, D d, E e], F);
+is_lambda!(IsLambda6, lambda6, lambda6_deps, [A a, B b, C c, D d, E e, F f], G);
+is_lambda!(IsLambda7, lambda7, lambda7_deps, [A a, B b, C c, D d, E e, F f, G g], H);
+is_lambda!(IsLambda8, lambda8, lambda8_deps, [A a, B b, C c, D d, E e, F f, G g, H h], I);
+is_lambda!(IsLambda9, lambda9, lambda9_deps, [A a, B b, C c, D d, E e, F f, G g, H h, I i], J);
+is_lambda!(IsLambda10, lambda10, lambda10_deps, [A a, B b, C c, D d, E e, F f, G g, H h, I i, J j], K);
+is_lambda!(IsLambda11, lambda11, lambda11_deps, [A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k], L);
+is_lambda!(IsLambda12, lambda12, lambda12_deps, [A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l], M);
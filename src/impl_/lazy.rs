@@ -1,8 +1,16 @@
+use crate::impl_::poison_policy::PoisonPolicy;
+use crate::impl_::sodium_error::SodiumError;
+
+use std::mem;
 use std::sync::Arc;
+use std::sync::Condvar;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::thread::ThreadId;
 
 pub struct Lazy<A> {
-    data: Arc<Mutex<LazyData<A>>>
+    data: Arc<LazyCell<A>>
 }
 
 impl<A> Clone for Lazy<A> {
@@ -13,43 +21,150 @@ impl<A> Clone for Lazy<A> {
     }
 }
 
+pub struct LazyCell<A> {
+    data: Mutex<LazyData<A>>,
+    // signalled whenever a thunk finishes running, for other threads waiting on its value
+    ready: Condvar
+}
+
 pub enum LazyData<A> {
-    Thunk(Box<dyn FnMut()->A+Send>),
+    Thunk(Box<dyn FnMut()->Result<A,SodiumError>+Send>),
+    // the thunk has been taken out to be run by this thread, the lock is not held while it runs
+    Running(ThreadId),
+    // the thunk panicked, and is left in place to be run again once the panic is recovered from
+    Poisoned(Box<dyn FnMut()->Result<A,SodiumError>+Send>),
     Value(A)
 }
 
-impl<A:Send+Clone+'static> Lazy<A> {
+// puts the thunk back if it fails or panics
+struct RunningGuard<'a,A> {
+    lazy: &'a Lazy<A>,
+    thunk_op: Option<Box<dyn FnMut()->Result<A,SodiumError>+Send>>
+}
 
-    pub fn new<THUNK:FnMut()->A+Send+'static>(thunk: THUNK) -> Lazy<A> {
-        Lazy {
-            data: Arc::new(Mutex::new(LazyData::Thunk(Box::new(thunk))))
+impl<'a,A> Drop for RunningGuard<'a,A> {
+    fn drop(&mut self) {
+        if let Some(thunk) = self.thunk_op.take() {
+            let mut l = self.lazy.data.data.lock().unwrap_or_else(|err| err.into_inner());
+            *l = if thread::panicking() { LazyData::Poisoned(thunk) } else { LazyData::Thunk(thunk) };
+            self.lazy.data.ready.notify_all();
         }
     }
+}
+
+impl<A:Send+Clone+'static> Lazy<A> {
+
+    pub fn new<THUNK:FnMut()->A+Send+'static>(mut thunk: THUNK) -> Lazy<A> {
+        Lazy::new_fallible(move || Ok(thunk()))
+    }
+
+    // a thunk that may fail, errors are not cached so the thunk runs again on the next run
+    pub fn new_fallible<THUNK:FnMut()->Result<A,SodiumError>+Send+'static>(thunk: THUNK) -> Lazy<A> {
+        Lazy::_new(LazyData::Thunk(Box::new(thunk)))
+    }
 
     pub fn of_value(value: A) -> Lazy<A> {
+        Lazy::_new(LazyData::Value(value))
+    }
+
+    fn _new(data: LazyData<A>) -> Lazy<A> {
         Lazy {
-            data: Arc::new(Mutex::new(LazyData::Value(value)))
+            data: Arc::new(LazyCell {
+                data: Mutex::new(data),
+                ready: Condvar::new()
+            })
         }
     }
 
+    pub fn map<B:Send+Clone+'static,FN:FnMut(&A)->B+Send+'static>(&self, mut f: FN) -> Lazy<B> {
+        let self_ = self.clone();
+        Lazy::new_fallible(move || Ok(f(&self_.try_run()?)))
+    }
+
+    pub fn lift2<B:Send+Clone+'static,C:Send+Clone+'static,FN:FnMut(&A,&B)->C+Send+'static>(&self, lb: &Lazy<B>, mut f: FN) -> Lazy<C> {
+        let self_ = self.clone();
+        let lb = lb.clone();
+        Lazy::new_fallible(move || Ok(f(&self_.try_run()?, &lb.try_run()?)))
+    }
+
+    pub fn flat_map<B:Send+Clone+'static,FN:FnMut(&A)->Lazy<B>+Send+'static>(&self, mut f: FN) -> Lazy<B> {
+        let self_ = self.clone();
+        Lazy::new_fallible(move || f(&self_.try_run()?).try_run())
+    }
+
     pub fn run(&self) -> A {
-        let mut l = self.data.lock();
-        let data: &mut LazyData<A> = l.as_mut().unwrap();
-        let next_op: Option<LazyData<A>>;
-        let result: A;
-        match data {
-            &mut LazyData::Thunk(ref mut k) => {
-                result = k();
-                next_op = Some(LazyData::Value(result.clone()));
-            },
-            &mut LazyData::Value(ref x) => {
-                result = x.clone();
-                next_op = None;
+        self.run_with_poison_policy(PoisonPolicy::Propagate)
+    }
+
+    pub fn run_with_poison_policy(&self, poison_policy: PoisonPolicy) -> A {
+        match self.try_run_with_poison_policy(poison_policy) {
+            Ok(result) => result,
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    pub fn try_run(&self) -> Result<A,SodiumError> {
+        self.try_run_with_poison_policy(PoisonPolicy::Propagate)
+    }
+
+    // The thunk runs without the lock held, so it may run other lazy values, including ones that
+    // lead back to this one. That is reported as SodiumError::LazyCycle rather than deadlocking. A
+    // thunk that panicked is left in place, so recovering simply runs it again.
+    // Only cycles within one thread are detected. If two threads each run a thunk that needs the
+    // value the other is running, both wait on the Condvar forever.
+    pub fn try_run_with_poison_policy(&self, poison_policy: PoisonPolicy) -> Result<A,SodiumError> {
+        let this_thread = thread::current().id();
+        let thunk;
+        {
+            let mut l = self.lock(poison_policy)?;
+            loop {
+                match *l {
+                    LazyData::Value(ref x) => return Ok(x.clone()),
+                    LazyData::Running(thread_id) => {
+                        if thread_id == this_thread {
+                            return Err(SodiumError::LazyCycle);
+                        }
+                        // another thread is running the thunk, wait for its value
+                        l = match self.data.ready.wait(l) {
+                            Ok(l) => l,
+                            Err(_) => return Err(SodiumError::Poisoned)
+                        };
+                    },
+                    LazyData::Poisoned(_) => {
+                        if poison_policy == PoisonPolicy::Propagate {
+                            return Err(SodiumError::Poisoned);
+                        }
+                        break;
+                    },
+                    LazyData::Thunk(_) => break
+                }
             }
+            thunk = match mem::replace(&mut *l, LazyData::Running(this_thread)) {
+                LazyData::Thunk(thunk) | LazyData::Poisoned(thunk) => thunk,
+                _ => unreachable!()
+            };
         }
-        if let Some(next) = next_op {
-            *data = next;
+        let mut running_guard = RunningGuard { lazy: self, thunk_op: Some(thunk) };
+        let result = (running_guard.thunk_op.as_mut().unwrap())()?;
+        running_guard.thunk_op = None;
+        {
+            let mut l = self.data.data.lock().unwrap_or_else(|err| err.into_inner());
+            *l = LazyData::Value(result.clone());
+        }
+        self.data.ready.notify_all();
+        Ok(result)
+    }
+
+    fn lock(&self, poison_policy: PoisonPolicy) -> Result<MutexGuard<'_,LazyData<A>>,SodiumError> {
+        match self.data.data.lock() {
+            Ok(l) => Ok(l),
+            Err(err) => {
+                if poison_policy == PoisonPolicy::Propagate {
+                    return Err(SodiumError::Poisoned);
+                }
+                self.data.data.clear_poison();
+                Ok(err.into_inner())
+            }
         }
-        result
     }
 }
@@ -1,8 +1,10 @@
 use crate::impl_::gc_node::{GcNode, Tracer};
 use crate::impl_::node::{Node, IsNode};
+use crate::impl_::poison_policy;
 use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::sodium_ctx::SodiumCtxData;
 
+use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::fmt;
@@ -31,15 +33,26 @@ impl Drop for Listener {
 pub struct ListenerData {
     pub sodium_ctx: SodiumCtx,
     pub is_weak: bool,
-    pub node_op: Option<Node>
+    pub node_op: Option<Node>,
+    pub children: Vec<Listener>
 }
 
 impl Listener {
     pub fn new(sodium_ctx: &SodiumCtx, is_weak: bool, node: Node) -> Listener {
+        Listener::_new(sodium_ctx, is_weak, Some(node), Vec::new())
+    }
+
+    // a listener that unlistens all of the given listeners when unlistened
+    pub fn new_combined(sodium_ctx: &SodiumCtx, listeners: Vec<Listener>) -> Listener {
+        Listener::_new(sodium_ctx, true, None, listeners)
+    }
+
+    fn _new(sodium_ctx: &SodiumCtx, is_weak: bool, node_op: Option<Node>, children: Vec<Listener>) -> Listener {
         let listener_data = Arc::new(Mutex::new(ListenerData {
             sodium_ctx: sodium_ctx.clone(),
-            node_op: Some(node),
-            is_weak
+            node_op,
+            is_weak,
+            children
         }));
         let gc_node_desconstructor;
         {
@@ -48,6 +61,7 @@ impl Listener {
                 let mut l = listener_data.lock();
                 let listener_data = l.as_mut().unwrap();
                 listener_data.node_op = None;
+                listener_data.children.clear();
             };
         }
         let gc_node_trace;
@@ -57,6 +71,7 @@ impl Listener {
                 let mut l = listener_data.lock();
                 let listener_data = l.as_mut().unwrap();
                 listener_data.node_op.iter().for_each(|node: &Node| tracer(&node.gc_node));
+                listener_data.children.iter().for_each(|child: &Listener| tracer(&child.gc_node));
             };
         }
         let listener = Listener {
@@ -74,27 +89,43 @@ impl Listener {
     pub fn unlisten(&self) {
         let is_weak;
         let sodium_ctx;
+        let children;
         {
             let mut l = self.data.lock();
             let data: &mut ListenerData = l.as_mut().unwrap();
             data.node_op = None;
             is_weak = data.is_weak;
             sodium_ctx = data.sodium_ctx.clone();
+            children = mem::take(&mut data.children);
+        }
+        for child in &children {
+            child.unlisten();
         }
         if !is_weak {
-            sodium_ctx.with_data(|data: &mut SodiumCtxData| {
-                data.keep_alive.retain(|l:&Listener| !Arc::ptr_eq(&l.data,&self.data))
-            });
+            // dropped outside of the lock, as freeing the listener may run cleanups that use the context
+            let _removed: Vec<Listener> =
+                sodium_ctx.with_data(|data: &mut SodiumCtxData| {
+                    let mut keep_alive = Vec::new();
+                    mem::swap(&mut keep_alive, &mut data.keep_alive);
+                    let (removed, kept) = keep_alive.into_iter().partition(|l:&Listener| Arc::ptr_eq(&l.data,&self.data));
+                    data.keep_alive = kept;
+                    removed
+                });
         }
     }
 
+    pub fn append(&self, other: &Listener) -> Listener {
+        let sodium_ctx = self.with_data(|data: &mut ListenerData| data.sodium_ctx.clone());
+        Listener::new_combined(&sodium_ctx, vec![self.clone(), other.clone()])
+    }
+
     pub fn node_op(&self) -> Option<Node> {
         self.with_data(|data: &mut ListenerData| data.node_op.clone())
     }
 
     pub fn with_data<R,K:FnOnce(&mut ListenerData)->R>(&self, k: K) -> R {
-        let mut l = self.data.lock();
-        let data: &mut ListenerData = l.as_mut().unwrap();
+        let mut l = poison_policy::lock(&self.data, |data: &ListenerData| data.sodium_ctx.poison_policy());
+        let data: &mut ListenerData = &mut l;
         k(data)
     }
 }
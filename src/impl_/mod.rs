@@ -1,13 +1,21 @@
 pub mod cell;
 pub mod cell_loop;
 pub mod cell_sink;
+pub mod checkpoint;
 pub mod dep;
 pub mod gc_node;
 pub mod lambda;
 pub mod lazy;
 pub mod listener;
 pub mod node;
+pub mod node_profile;
+pub mod poison_policy;
+pub mod shared_tree;
 pub mod sodium_ctx;
+pub mod sodium_error;
 pub mod stream;
 pub mod stream_loop;
 pub mod stream_sink;
+pub mod timer;
+pub mod transaction_hooks;
+pub mod transaction_lock;
@@ -68,6 +68,12 @@ impl dyn IsNode {
         let mut keep_alive = self.data().keep_alive.write().unwrap();
         keep_alive.push(gc_node.clone());
     }
+
+    // runs once when this node is freed
+    pub fn add_cleanup<CLEANUP:FnOnce()+Send+Sync+'static>(&self, cleanup: CLEANUP) {
+        let mut cleanups = self.data().cleanups.write().unwrap();
+        cleanups.push(Box::new(cleanup));
+    }
 }
 
 pub trait IsWeakNode: Send + Sync {
@@ -144,14 +150,33 @@ pub struct NodeData {
     pub dependencies: RwLock<Vec<Box<dyn IsNode+Send+Sync>>>,
     pub dependents: RwLock<Vec<Box<dyn IsWeakNode+Send+Sync>>>,
     pub keep_alive: RwLock<Vec<GcNode>>,
+    pub cleanups: RwLock<Vec<Box<dyn FnOnce()+Send+Sync>>>,
+    // set by Stream::named and Cell::named, to tell nodes apart in the profile report
+    pub label: RwLock<Option<String>>,
+    pub gc_id: u32,
     pub sodium_ctx: SodiumCtx
 }
 
+// The context is taken from the node's data on upgrade, so a weak reference left in a pending
+// timer doesn't keep the context alive.
 #[derive(Clone)]
 pub struct WeakNode {
     pub data: Weak<NodeData>,
-    pub gc_node: GcNode,
-    pub sodium_ctx: SodiumCtx
+    pub gc_node: GcNode
+}
+
+impl NodeData {
+    // true if gc_node is one of this node's dependencies or update dependencies
+    pub fn tracks(&self, gc_node: &GcNode) -> bool {
+        {
+            let dependencies = self.dependencies.read().unwrap();
+            if dependencies.iter().any(|dependency| dependency.gc_node().ptr_eq(gc_node)) {
+                return true;
+            }
+        }
+        let update_dependencies = self.update_dependencies.read().unwrap();
+        update_dependencies.iter().any(|dep: &Dep| dep.gc_node().ptr_eq(gc_node))
+    }
 }
 
 impl Clone for Node {
@@ -175,6 +200,12 @@ impl Drop for Node {
 
 impl Drop for NodeData {
     fn drop(&mut self) {
+        // cleanups not already run by the gc deconstructor
+        let cleanups = std::mem::take(self.cleanups.get_mut().unwrap());
+        for cleanup in cleanups {
+            cleanup();
+        }
+        self.sodium_ctx.forget_profile(self.gc_id);
         self.sodium_ctx.dec_node_count();
     }
 }
@@ -241,6 +272,14 @@ impl Node {
                 for gc_node in keep_alive {
                     gc_node.dec_ref();
                 }
+                let mut cleanups = Vec::new();
+                {
+                    let mut cleanups2 = node_data.cleanups.write().unwrap();
+                    std::mem::swap(&mut *cleanups2, &mut cleanups);
+                }
+                for cleanup in cleanups {
+                    cleanup();
+                }
                 {
                     let mut node = result_forward_ref.write().unwrap();
                     *node = None;
@@ -279,6 +318,13 @@ impl Node {
                 }
             };
         }
+        let gc_node =
+            GcNode::new(
+                &sodium_ctx.gc_ctx(),
+                name.to_string(),
+                deconstructor,
+                trace
+            );
         let result =
             Node {
                 data:
@@ -290,14 +336,12 @@ impl Node {
                         dependencies: RwLock::new(box_clone_vec_is_node(&dependencies)),
                         dependents: RwLock::new(Vec::new()),
                         keep_alive: RwLock::new(Vec::new()),
+                        cleanups: RwLock::new(Vec::new()),
+                        label: RwLock::new(None),
+                        gc_id: gc_node.id(),
                         sodium_ctx: sodium_ctx.clone()
                     }),
-                gc_node: GcNode::new(
-                    &sodium_ctx.gc_ctx(),
-                    name.to_string(),
-                    deconstructor,
-                    trace
-                ),
+                gc_node,
                 sodium_ctx: sodium_ctx.clone()
             };
         {
@@ -313,11 +357,15 @@ impl Node {
         return result;
     }
 
+    pub fn set_label(&self, label: &str) {
+        let mut label2 = self.data.label.write().unwrap();
+        *label2 = Some(label.to_string());
+    }
+
     pub fn downgrade2(this: &Self) -> WeakNode {
         WeakNode {
             data: Arc::downgrade(&this.data),
-            gc_node: this.gc_node.clone(),
-            sodium_ctx: this.sodium_ctx.clone()
+            gc_node: this.gc_node.clone()
         }
     }
 }
@@ -325,12 +373,13 @@ impl Node {
 impl WeakNode {
     pub fn upgrade2(&self) -> Option<Node> {
         if let Some(data) = self.data.upgrade() {
+            let sodium_ctx = data.sodium_ctx.clone();
             self.gc_node.inc_ref();
-            self.sodium_ctx.inc_node_ref_count();
+            sodium_ctx.inc_node_ref_count();
             Some(Node {
                 data,
                 gc_node: self.gc_node.clone(),
-                sodium_ctx: self.sodium_ctx.clone()
+                sodium_ctx
             })
         } else {
             None
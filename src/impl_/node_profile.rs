@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+// How many times one node's update ran while profiling was on, and the wall time it took in total.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeProfile {
+    pub node_id: u32,
+    pub name: String,
+    // given by Stream::named or Cell::named
+    pub label: Option<String>,
+    pub updates: u64,
+    pub total_time: Duration
+}
@@ -0,0 +1,48 @@
+use crate::impl_::sodium_error::SodiumError;
+
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::RwLock;
+use std::sync::RwLockWriteGuard;
+
+// What to do when a lock is found poisoned because a listener or lambda panicked while holding it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    // panic again on every later access, this is the default
+    Propagate,
+    // clear the poison and carry on with the state as the panicking thread left it
+    Recover
+}
+
+// Locks the mutex, consulting the policy only if it turns out to be poisoned. The policy is
+// read from the locked data itself, since that is often where the SodiumCtx lives.
+pub fn lock<'a,T,POLICY:FnOnce(&T)->PoisonPolicy>(mutex: &'a Mutex<T>, policy: POLICY) -> MutexGuard<'a,T> {
+    match mutex.lock() {
+        Ok(l) => l,
+        Err(err) => {
+            let l = err.into_inner();
+            match policy(&l) {
+                PoisonPolicy::Propagate => panic!("{}", SodiumError::Poisoned),
+                PoisonPolicy::Recover => {
+                    mutex.clear_poison();
+                    l
+                }
+            }
+        }
+    }
+}
+
+pub fn write<T>(rw_lock: &RwLock<T>, policy: PoisonPolicy) -> RwLockWriteGuard<'_,T> {
+    match rw_lock.write() {
+        Ok(l) => l,
+        Err(err) => {
+            match policy {
+                PoisonPolicy::Propagate => panic!("{}", SodiumError::Poisoned),
+                PoisonPolicy::Recover => {
+                    rw_lock.clear_poison();
+                    err.into_inner()
+                }
+            }
+        }
+    }
+}
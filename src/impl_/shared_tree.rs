@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// A balanced tree of elements addressed by position, the storage behind SharedVec and SharedMap.
+// Clones share all of their nodes, and an edit only copies the nodes on the path down to the
+// element it changes, so every version handed out by a cell stays valid at O(log n) per edit.
+// Each element carries a weight, and the sum of the weights before a position can be found in
+// O(log n) too, which is how CellVec::filter maps positions in its source to its own.
+pub struct SharedTree<T> {
+    root: Link<T>
+}
+
+type Link<T> = Option<Arc<TreeNode<T>>>;
+
+#[derive(Clone)]
+struct TreeNode<T> {
+    value: T,
+    weight: usize,
+    len: usize,
+    total_weight: usize,
+    height: usize,
+    left: Link<T>,
+    right: Link<T>
+}
+
+impl<T> Clone for SharedTree<T> {
+    fn clone(&self) -> Self {
+        SharedTree {
+            root: self.root.clone()
+        }
+    }
+}
+
+impl<T:Clone> SharedTree<T> {
+    pub fn new() -> SharedTree<T> {
+        SharedTree { root: None }
+    }
+
+    // takes (value, weight) pairs in order
+    pub fn from_vec(items: Vec<(T,usize)>) -> SharedTree<T> {
+        let len = items.len();
+        SharedTree { root: build(&mut items.into_iter(), len) }
+    }
+
+    pub fn len(&self) -> usize {
+        len(&self.root)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut link = &self.root;
+        let mut index = index;
+        while let Some(node) = link {
+            let left_len = len(&node.left);
+            match index.cmp(&left_len) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => {
+                    index -= left_len + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        None
+    }
+
+    pub fn weight(&self, index: usize) -> usize {
+        self.weight_before(index + 1) - self.weight_before(index)
+    }
+
+    // the sum of the weights of the elements before index
+    pub fn weight_before(&self, index: usize) -> usize {
+        let mut link = &self.root;
+        let mut index = index;
+        let mut result = 0;
+        while let Some(node) = link {
+            let left_len = len(&node.left);
+            if index <= left_len {
+                link = &node.left;
+            } else {
+                result += total_weight(&node.left) + node.weight;
+                index -= left_len + 1;
+                link = &node.right;
+            }
+        }
+        result
+    }
+
+    // like slice::binary_search_by, for trees kept in order
+    pub fn search_by<F:FnMut(&T)->Ordering>(&self, mut f: F) -> Result<usize,usize> {
+        let mut link = &self.root;
+        let mut base = 0;
+        while let Some(node) = link {
+            match f(&node.value) {
+                Ordering::Less => {
+                    base += len(&node.left) + 1;
+                    link = &node.right;
+                },
+                Ordering::Greater => link = &node.left,
+                Ordering::Equal => return Ok(base + len(&node.left))
+            }
+        }
+        Err(base)
+    }
+
+    pub fn insert(&mut self, index: usize, value: T, weight: usize) {
+        assert!(index <= self.len(), "insertion index {} is out of bounds for length {}", index, self.len());
+        self.root = Some(insert(self.root.take(), index, value, weight));
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "removal index {} is out of bounds for length {}", index, self.len());
+        let (root, value) = remove(self.root.take().unwrap(), index);
+        self.root = root;
+        value
+    }
+
+    pub fn set(&mut self, index: usize, value: T, weight: usize) {
+        assert!(index < self.len(), "index {} is out of bounds for length {}", index, self.len());
+        set(self.root.as_mut().unwrap(), index, value, weight);
+    }
+
+    pub fn iter(&self) -> SharedTreeIter<'_,T> {
+        let mut iter = SharedTreeIter { stack: Vec::new() };
+        iter.push_left(&self.root);
+        iter
+    }
+}
+
+pub struct SharedTreeIter<'a,T> {
+    stack: Vec<&'a TreeNode<T>>
+}
+
+impl<'a,T> SharedTreeIter<'a,T> {
+    fn push_left(&mut self, link: &'a Link<T>) {
+        let mut link = link;
+        while let Some(node) = link {
+            self.stack.push(node);
+            link = &node.left;
+        }
+    }
+}
+
+impl<'a,T> Iterator for SharedTreeIter<'a,T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        Some(&node.value)
+    }
+}
+
+fn len<T>(link: &Link<T>) -> usize {
+    link.as_ref().map(|node| node.len).unwrap_or(0)
+}
+
+fn total_weight<T>(link: &Link<T>) -> usize {
+    link.as_ref().map(|node| node.total_weight).unwrap_or(0)
+}
+
+fn height<T>(link: &Link<T>) -> usize {
+    link.as_ref().map(|node| node.height).unwrap_or(0)
+}
+
+fn leaf<T>(value: T, weight: usize) -> Arc<TreeNode<T>> {
+    Arc::new(TreeNode { value, weight, len: 1, total_weight: weight, height: 1, left: None, right: None })
+}
+
+fn build<T,I:Iterator<Item=(T,usize)>>(items: &mut I, count: usize) -> Link<T> {
+    if count == 0 {
+        return None;
+    }
+    let left = build(items, count / 2);
+    let (value, weight) = items.next().unwrap();
+    let right = build(items, count - count / 2 - 1);
+    let mut node = TreeNode { value, weight, len: 0, total_weight: 0, height: 0, left, right };
+    update(&mut node);
+    Some(Arc::new(node))
+}
+
+fn update<T>(node: &mut TreeNode<T>) {
+    node.len = len(&node.left) + 1 + len(&node.right);
+    node.total_weight = total_weight(&node.left) + node.weight + total_weight(&node.right);
+    node.height = 1 + height(&node.left).max(height(&node.right));
+}
+
+// Arc::make_mut only copies a node if another version still shares it
+fn rotate_right<T:Clone>(mut node: Arc<TreeNode<T>>) -> Arc<TreeNode<T>> {
+    let mut left;
+    {
+        let n = Arc::make_mut(&mut node);
+        left = n.left.take().unwrap();
+        let l = Arc::make_mut(&mut left);
+        n.left = l.right.take();
+        update(n);
+    }
+    {
+        let l = Arc::make_mut(&mut left);
+        l.right = Some(node);
+        update(l);
+    }
+    left
+}
+
+fn rotate_left<T:Clone>(mut node: Arc<TreeNode<T>>) -> Arc<TreeNode<T>> {
+    let mut right;
+    {
+        let n = Arc::make_mut(&mut node);
+        right = n.right.take().unwrap();
+        let r = Arc::make_mut(&mut right);
+        n.right = r.left.take();
+        update(n);
+    }
+    {
+        let r = Arc::make_mut(&mut right);
+        r.left = Some(node);
+        update(r);
+    }
+    right
+}
+
+fn balance<T:Clone>(mut node: Arc<TreeNode<T>>) -> Arc<TreeNode<T>> {
+    update(Arc::make_mut(&mut node));
+    let left_height = height(&node.left);
+    let right_height = height(&node.right);
+    if left_height > right_height + 1 {
+        let n = Arc::make_mut(&mut node);
+        let left = n.left.take().unwrap();
+        n.left = Some(if height(&left.left) < height(&left.right) { rotate_left(left) } else { left });
+        rotate_right(node)
+    } else if right_height > left_height + 1 {
+        let n = Arc::make_mut(&mut node);
+        let right = n.right.take().unwrap();
+        n.right = Some(if height(&right.right) < height(&right.left) { rotate_right(right) } else { right });
+        rotate_left(node)
+    } else {
+        node
+    }
+}
+
+fn insert<T:Clone>(link: Link<T>, index: usize, value: T, weight: usize) -> Arc<TreeNode<T>> {
+    match link {
+        None => leaf(value, weight),
+        Some(mut node) => {
+            {
+                let n = Arc::make_mut(&mut node);
+                let left_len = len(&n.left);
+                if index <= left_len {
+                    n.left = Some(insert(n.left.take(), index, value, weight));
+                } else {
+                    n.right = Some(insert(n.right.take(), index - left_len - 1, value, weight));
+                }
+            }
+            balance(node)
+        }
+    }
+}
+
+fn remove<T:Clone>(mut node: Arc<TreeNode<T>>, index: usize) -> (Link<T>, T) {
+    let n = Arc::make_mut(&mut node);
+    let left_len = len(&n.left);
+    match index.cmp(&left_len) {
+        Ordering::Less => {
+            let (left, value) = remove(n.left.take().unwrap(), index);
+            n.left = left;
+            (Some(balance(node)), value)
+        },
+        Ordering::Greater => {
+            let (right, value) = remove(n.right.take().unwrap(), index - left_len - 1);
+            n.right = right;
+            (Some(balance(node)), value)
+        },
+        Ordering::Equal => {
+            match (n.left.take(), n.right.take()) {
+                (None, right) => (right, n.value.clone()),
+                (left, None) => (left, n.value.clone()),
+                (left, Some(right)) => {
+                    // the next element takes this one's place
+                    let (right, (next_value, next_weight)) = remove_first(right);
+                    let value = std::mem::replace(&mut n.value, next_value);
+                    n.weight = next_weight;
+                    n.left = left;
+                    n.right = right;
+                    (Some(balance(node)), value)
+                }
+            }
+        }
+    }
+}
+
+fn remove_first<T:Clone>(mut node: Arc<TreeNode<T>>) -> (Link<T>, (T,usize)) {
+    let n = Arc::make_mut(&mut node);
+    match n.left.take() {
+        None => (n.right.take(), (n.value.clone(), n.weight)),
+        Some(left) => {
+            let (left, first) = remove_first(left);
+            n.left = left;
+            (Some(balance(node)), first)
+        }
+    }
+}
+
+fn set<T:Clone>(node: &mut Arc<TreeNode<T>>, index: usize, value: T, weight: usize) {
+    let n = Arc::make_mut(node);
+    let left_len = len(&n.left);
+    match index.cmp(&left_len) {
+        Ordering::Less => set(n.left.as_mut().unwrap(), index, value, weight),
+        Ordering::Greater => set(n.right.as_mut().unwrap(), index - left_len - 1, value, weight),
+        Ordering::Equal => {
+            n.value = value;
+            n.weight = weight;
+        }
+    }
+    update(n);
+}
@@ -1,11 +1,35 @@
-use crate::impl_::gc_node::GcCtx;
+use crate::impl_::checkpoint::Checkpoint;
+use crate::impl_::gc_node::{GcCtx, GcNode};
 use crate::impl_::listener::Listener;
+use crate::impl_::poison_policy::{self, PoisonPolicy};
+use crate::impl_::sodium_error::SodiumError;
+use crate::impl_::timer::Clock;
+use crate::impl_::timer::Timer;
+use crate::impl_::timer::Timers;
+use crate::impl_::transaction_hooks::TransactionHook;
+use crate::impl_::transaction_hooks::TransactionHooks;
+use crate::impl_::transaction_hooks::TransactionCounters;
+use crate::impl_::transaction_hooks::TransactionStats;
+use crate::impl_::transaction_lock::TransactionLock;
+use crate::impl_::node_profile::NodeProfile;
 use crate::impl_::node::{Node, IsNode, IsWeakNode, box_clone_vec_is_node, box_clone_vec_is_weak_node};
+#[cfg(debug_assertions)]
+use crate::impl_::node::NodeData;
 
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
 use std::mem;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct SodiumCtx {
@@ -13,19 +37,65 @@ pub struct SodiumCtx {
     data: Arc<Mutex<SodiumCtxData>>,
     node_count: Arc<Mutex<usize>>,
     node_ref_count: Arc<Mutex<usize>>,
+    // read on every node update, so kept out of SodiumCtxData and its lock
+    profiling: Arc<AtomicBool>,
+    counters: Arc<TransactionCounters>,
+    // per node update counts and times by gc node id, only recorded while profiling is on. Under
+    // its own lock, as a node removes its entry when it is dropped.
+    profile: Arc<Mutex<HashMap<u32,NodeProfile>>>,
+    poison_policy: Arc<Mutex<PoisonPolicy>>,
+    transaction_lock: Arc<TransactionLock>,
     threaded_mode: Arc<ThreadedMode>
 }
 
+// For timers that schedule the next one, which would otherwise keep the context alive from its own
+// list of pending timers.
+#[derive(Clone)]
+pub struct WeakSodiumCtx {
+    gc_ctx: GcCtx,
+    data: Weak<Mutex<SodiumCtxData>>,
+    node_count: Weak<Mutex<usize>>,
+    node_ref_count: Weak<Mutex<usize>>,
+    profiling: Weak<AtomicBool>,
+    counters: Weak<TransactionCounters>,
+    profile: Weak<Mutex<HashMap<u32,NodeProfile>>>,
+    poison_policy: Weak<Mutex<PoisonPolicy>>,
+    transaction_lock: Weak<TransactionLock>,
+    threaded_mode: Weak<ThreadedMode>
+}
+
+pub type CheckpointEncoder = Box<dyn Fn()->Option<Vec<u8>>+Send>;
+
+pub type AsyncSpawner = Arc<dyn Fn(Pin<Box<dyn Future<Output=()>+Send>>)+Send+Sync>;
+
 pub struct SodiumCtxData {
     pub changed_nodes: Vec<Box<dyn IsNode>>,
     pub visited_nodes: Vec<Box<dyn IsNode>>,
     pub transaction_depth: u32,
+    // counts outermost transactions, so everything done inside one can be told apart from the next
+    pub transaction_id: u64,
     pub pre_post: Vec<Box<dyn FnMut()+Send>>,
     pub post: Vec<Box<dyn FnMut()+Send>>,
     pub keep_alive: Vec<Listener>,
     pub collecting_cycles: bool,
     pub allow_add_roots: bool,
-    pub allow_collect_cycles_counter: u32
+    pub allow_collect_cycles_counter: u32,
+    pub panic_on_untracked_deps: bool,
+    // loops created inside the current transaction that have not been looped yet
+    pub unlooped: Vec<GcNode>,
+    // set when a transaction unwound from a panic under PoisonPolicy::Recover, its firing state
+    // still needs clearing before the next transaction starts
+    pub aborted: bool,
+    // encoders for the current values of persistent cells by name, None once the cell is gone
+    pub checkpointed: Vec<(String,CheckpointEncoder)>,
+    // values restored from a checkpoint, waiting for their persistent cells to be created
+    pub restored: Checkpoint,
+    // the counters of the last outermost transaction to end, for cycle collections run outside one
+    pub last_stats: TransactionStats,
+    pub hooks: TransactionHooks,
+    pub timers: Timers,
+    // runs the futures of map_async, set by the application
+    pub async_spawner_op: Option<AsyncSpawner>
 }
 
 pub struct ThreadedMode {
@@ -102,6 +172,125 @@ pub fn simple_threaded_mode() -> ThreadedMode {
 // TODO:
 //pub fn thread_pool_threaded_mode(num_threads: usize) -> ThreadedMode
 
+// The nodes whose update closures are currently running on this thread, innermost last.
+#[cfg(debug_assertions)]
+thread_local! {
+    static UPDATING_NODES: RefCell<Vec<(GcNode, Arc<NodeData>)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(debug_assertions)]
+struct UpdatingNodeGuard {}
+
+#[cfg(debug_assertions)]
+impl UpdatingNodeGuard {
+    fn new(node: &Node) -> UpdatingNodeGuard {
+        UPDATING_NODES.with(|nodes| nodes.borrow_mut().push((node.gc_node.clone(), node.data.clone())));
+        UpdatingNodeGuard {}
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for UpdatingNodeGuard {
+    fn drop(&mut self) {
+        UPDATING_NODES.with(|nodes| { nodes.borrow_mut().pop(); });
+    }
+}
+
+// Only does something when a transaction is unwinding and the context is set to recover.
+struct TransactionPanicGuard<'a> {
+    sodium_ctx: &'a SodiumCtx
+}
+
+impl<'a> Drop for TransactionPanicGuard<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            if self.sodium_ctx.poison_policy() == PoisonPolicy::Recover {
+                self.sodium_ctx.abort_transaction();
+            }
+            self.sodium_ctx.transaction_lock.release_all();
+        }
+    }
+}
+
+// An open transaction, see SodiumCtx::begin. Dropping it commits it, and panics if that fails.
+pub struct TransactionGuard {
+    sodium_ctx: SodiumCtx,
+    open: bool
+}
+
+impl TransactionGuard {
+    pub fn commit(mut self) -> Result<(),SodiumError> {
+        self.open = false;
+        self.sodium_ctx.close_transaction()
+    }
+
+    // Discards the values sent since begin, nothing fires and no cell changes. Only the outermost
+    // transaction can be aborted, aborting a nested one closes it and returns SodiumError::AbortNested.
+    pub fn abort(mut self) -> Result<(),SodiumError> {
+        self.open = false;
+        self.sodium_ctx.discard_transaction()
+    }
+}
+
+impl Drop for TransactionGuard {
+    fn drop(&mut self) {
+        // a panic out of commit has left the transaction half closed, so it is aborted too
+        if thread::panicking() {
+            if self.sodium_ctx.poison_policy() == PoisonPolicy::Recover {
+                self.sodium_ctx.abort_transaction();
+            }
+            self.sodium_ctx.transaction_lock.release_all();
+            return;
+        }
+        if !self.open {
+            return;
+        }
+        if let Err(err) = self.sodium_ctx.close_transaction() {
+            panic!("{}", err);
+        }
+    }
+}
+
+struct BatchGuard<'a> {
+    sodium_ctx: &'a SodiumCtx
+}
+
+impl<'a> Drop for BatchGuard<'a> {
+    fn drop(&mut self) {
+        // an aborted transaction resets the counter, so it may already be 0
+        self.sodium_ctx.with_data(|data: &mut SodiumCtxData| {
+            data.allow_collect_cycles_counter = data.allow_collect_cycles_counter.saturating_sub(1);
+        });
+    }
+}
+
+struct TransactionLockGuard<'a> {
+    sodium_ctx: &'a SodiumCtx
+}
+
+impl<'a> Drop for TransactionLockGuard<'a> {
+    fn drop(&mut self) {
+        self.sodium_ctx.transaction_lock.release();
+    }
+}
+
+impl WeakSodiumCtx {
+    pub fn upgrade(&self) -> Option<SodiumCtx> {
+        Some(SodiumCtx {
+            gc_ctx: self.gc_ctx.clone(),
+            data: self.data.upgrade()?,
+            node_count: self.node_count.upgrade()?,
+            node_ref_count: self.node_ref_count.upgrade()?,
+            profiling: self.profiling.upgrade()?,
+            counters: self.counters.upgrade()?,
+            profile: self.profile.upgrade()?,
+            poison_policy: self.poison_policy.upgrade()?,
+            transaction_lock: self.transaction_lock.upgrade()?,
+            threaded_mode: self.threaded_mode.upgrade()?
+        })
+    }
+}
+
 impl SodiumCtx {
     pub fn new() -> SodiumCtx {
         SodiumCtx {
@@ -112,20 +301,50 @@ impl SodiumCtx {
                         changed_nodes: Vec::new(),
                         visited_nodes: Vec::new(),
                         transaction_depth: 0,
+                        transaction_id: 0,
                         pre_post: Vec::new(),
                         post: Vec::new(),
                         keep_alive: Vec::new(),
                         collecting_cycles: false,
                         allow_add_roots: true,
-                        allow_collect_cycles_counter: 0
+                        allow_collect_cycles_counter: 0,
+                        panic_on_untracked_deps: false,
+                        unlooped: Vec::new(),
+                        aborted: false,
+                        checkpointed: Vec::new(),
+                        restored: Checkpoint::new(),
+                        last_stats: TransactionStats::default(),
+                        hooks: TransactionHooks::default(),
+                        timers: Timers::new(),
+                        async_spawner_op: None
                     }
                 )),
             node_count: Arc::new(Mutex::new(0)),
             node_ref_count: Arc::new(Mutex::new(0)),
+            profiling: Arc::new(AtomicBool::new(false)),
+            counters: Arc::new(TransactionCounters::default()),
+            profile: Arc::new(Mutex::new(HashMap::new())),
+            poison_policy: Arc::new(Mutex::new(PoisonPolicy::Propagate)),
+            transaction_lock: Arc::new(TransactionLock::new()),
             threaded_mode: Arc::new(single_threaded_mode())
         }
     }
 
+    pub fn downgrade(this: &Self) -> WeakSodiumCtx {
+        WeakSodiumCtx {
+            gc_ctx: this.gc_ctx.clone(),
+            data: Arc::downgrade(&this.data),
+            node_count: Arc::downgrade(&this.node_count),
+            node_ref_count: Arc::downgrade(&this.node_ref_count),
+            profiling: Arc::downgrade(&this.profiling),
+            counters: Arc::downgrade(&this.counters),
+            profile: Arc::downgrade(&this.profile),
+            poison_policy: Arc::downgrade(&this.poison_policy),
+            transaction_lock: Arc::downgrade(&this.transaction_lock),
+            threaded_mode: Arc::downgrade(&this.threaded_mode)
+        }
+    }
+
     pub fn gc_ctx(&self) -> GcCtx {
         self.gc_ctx.clone()
     }
@@ -135,19 +354,114 @@ impl SodiumCtx {
     }
 
     pub fn transaction<R,K:FnOnce()->R>(&self, k:K) -> R {
-        self.with_data(|data: &mut SodiumCtxData| {
-            data.transaction_depth = data.transaction_depth + 1;
-        });
+        match self.try_transaction(k) {
+            Ok(result) => result,
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    pub fn try_transaction<R,K:FnOnce()->R>(&self, k:K) -> Result<R,SodiumError> {
+        self.open_transaction();
+        let _transaction_guard = TransactionPanicGuard { sodium_ctx: self };
         let result = k();
-        let is_end_of_transaction =
+        self.close_transaction()?;
+        Ok(result)
+    }
+
+    // Opens a transaction that stays open until the returned guard is committed, aborted or dropped.
+    pub fn begin(&self) -> TransactionGuard {
+        self.open_transaction();
+        TransactionGuard {
+            sodium_ctx: self.clone(),
+            open: true
+        }
+    }
+
+    fn open_transaction(&self) {
+        self.transaction_lock.acquire();
+        let aborted = self.with_data(|data: &mut SodiumCtxData| mem::replace(&mut data.aborted, false));
+        if aborted {
+            self.finish_aborted_transaction();
+        }
+        let started_op =
+            self.with_data(|data: &mut SodiumCtxData| {
+                data.transaction_depth = data.transaction_depth + 1;
+                if data.transaction_depth != 1 {
+                    return None;
+                }
+                data.transaction_id = data.transaction_id + 1;
+                self.counters.reset();
+                Some(TransactionStats { transaction_id: data.transaction_id, ..TransactionStats::default() })
+            });
+        if let Some(stats) = started_op {
+            self.run_hooks(|hooks: &TransactionHooks| &hooks.start, &stats);
+        }
+    }
+
+    fn close_transaction(&self) -> Result<(),SodiumError> {
+        let _lock_guard = TransactionLockGuard { sodium_ctx: self };
+        let unlooped_op =
             self.with_data(|data: &mut SodiumCtxData| {
                 data.transaction_depth = data.transaction_depth - 1;
-                return data.transaction_depth == 0;
+                if data.transaction_depth == 0 {
+                    Some(mem::take(&mut data.unlooped))
+                } else {
+                    None
+                }
             });
-        if is_end_of_transaction {
-            self.end_of_transaction();
+        if let Some(unlooped) = unlooped_op {
+            self.end_of_transaction()?;
+            if !unlooped.is_empty() {
+                return Err(SodiumError::NotLooped(unlooped.len()));
+            }
         }
-        return result;
+        Ok(())
+    }
+
+    // Throws away everything sent in the outermost transaction before it gets propagated.
+    fn discard_transaction(&self) -> Result<(),SodiumError> {
+        let _lock_guard = TransactionLockGuard { sodium_ctx: self };
+        let discarded =
+            self.with_data(|data: &mut SodiumCtxData| {
+                if data.transaction_depth != 1 {
+                    data.transaction_depth = data.transaction_depth - 1;
+                    return None;
+                }
+                data.transaction_depth = 0;
+                data.unlooped.clear();
+                data.last_stats = self.counters.stats(data.transaction_id);
+                Some((mem::take(&mut data.pre_post), data.last_stats.clone()))
+            });
+        let (pre_post, stats) = match discarded {
+            Some(discarded) => discarded,
+            None => return Err(SodiumError::AbortNested)
+        };
+        // pre_post clears the firing values the sends left behind, post would only act on them
+        for mut k in pre_post {
+            k();
+        }
+        self.take_and_drop(|data: &mut SodiumCtxData| (mem::take(&mut data.post), mem::take(&mut data.changed_nodes)));
+        self.run_hooks(|hooks: &TransactionHooks| &hooks.end, &stats);
+        Ok(())
+    }
+
+    // Runs k, which makes several transactions of its own, with cycle collection held back until
+    // the last of them is done. The transaction lock is held throughout, so no other thread's
+    // transaction runs between them.
+    pub fn try_batch<R,K:FnOnce()->Result<R,SodiumError>>(&self, k: K) -> Result<R,SodiumError> {
+        self.transaction_lock.acquire();
+        let _lock_guard = TransactionLockGuard { sodium_ctx: self };
+        self.with_data(|data: &mut SodiumCtxData| data.allow_collect_cycles_counter = data.allow_collect_cycles_counter + 1);
+        let result;
+        {
+            let _batch_guard = BatchGuard { sodium_ctx: self };
+            result = k();
+        }
+        let allow_collect_cycles = self.with_data(|data: &mut SodiumCtxData| data.allow_collect_cycles_counter == 0);
+        if allow_collect_cycles {
+            self.try_collect_cycles()?;
+        }
+        result
     }
 
     pub fn add_dependents_to_changed_nodes(&self, node: &dyn IsNode) {
@@ -174,9 +488,129 @@ impl SodiumCtx {
         });
     }
 
+    // Debug builds only: reports gc_node being used from inside a node's update closure
+    // when it is not among that node's dependencies or update dependencies.
+    pub fn check_tracked(&self, gc_node: &GcNode, action: &str) {
+        #[cfg(debug_assertions)]
+        {
+            let untracked_in: Option<String> =
+                UPDATING_NODES.with(|nodes| {
+                    let nodes = nodes.borrow();
+                    nodes
+                        .last()
+                        .filter(|(node_gc_node, node_data)| !node_gc_node.ptr_eq(gc_node) && !node_data.tracks(gc_node))
+                        .map(|(node_gc_node, _)| format!("{} ({})", node_gc_node.id(), node_gc_node.name()))
+                });
+            if let Some(node_name) = untracked_in {
+                let msg = format!(
+                    "{} {} ({}) inside the update of node {} without declaring it as a dependency (see lambda1, lambda2, etc.)",
+                    action, gc_node.id(), gc_node.name(), node_name
+                );
+                if self.with_data(|data: &mut SodiumCtxData| data.panic_on_untracked_deps) {
+                    panic!("{}", msg);
+                }
+                warn!("{}", msg);
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = (gc_node, action);
+        }
+    }
+
+    pub fn transaction_id(&self) -> u64 {
+        self.with_data(|data: &mut SodiumCtxData| data.transaction_id)
+    }
+
+    pub fn poison_policy(&self) -> PoisonPolicy {
+        *self.poison_policy.lock().unwrap()
+    }
+
+    pub fn set_poison_policy(&self, poison_policy: PoisonPolicy) {
+        *self.poison_policy.lock().unwrap() = poison_policy;
+    }
+
+    // Called while a panic unwinds out of a transaction. Only resets the bookkeeping, the
+    // callbacks of the aborted transaction are left for finish_aborted_transaction.
+    fn abort_transaction(&self) {
+        self.take_and_drop(|data: &mut SodiumCtxData| {
+            data.transaction_depth = 0;
+            data.allow_collect_cycles_counter = 0;
+            data.unlooped.clear();
+            data.aborted = true;
+            (mem::take(&mut data.changed_nodes), mem::take(&mut data.visited_nodes))
+        });
+    }
+
+    // Throws away what an aborted transaction did, as discard_transaction does: pre_post clears
+    // the firing values it left behind, and post, which would commit them, is dropped.
+    fn finish_aborted_transaction(&self) {
+        let pre_post = self.with_data(|data: &mut SodiumCtxData| mem::take(&mut data.pre_post));
+        for mut k in pre_post {
+            k();
+        }
+        self.take_and_drop(|data: &mut SodiumCtxData| mem::take(&mut data.post));
+    }
+
+    // Drops what k takes out of the context's data once the lock is released, as dropping nodes
+    // can re-enter the context.
+    fn take_and_drop<T,K:FnOnce(&mut SodiumCtxData)->T>(&self, k: K) {
+        let taken = self.with_data(k);
+        drop(taken);
+    }
+
+    pub fn add_checkpointed<ENCODE:Fn()->Option<Vec<u8>>+Send+'static>(&self, name: String, encode: ENCODE) {
+        self.with_data(|data: &mut SodiumCtxData| {
+            if data.checkpointed.iter().any(|(name2, _)| *name2 == name) {
+                warn!("persistent cell {} registered more than once, keeping the latest", name);
+                data.checkpointed.retain(|(name2, _)| *name2 != name);
+            }
+            data.checkpointed.push((name, Box::new(encode)));
+        });
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        let checkpointed = self.with_data(|data: &mut SodiumCtxData| mem::take(&mut data.checkpointed));
+        let mut checkpoint = Checkpoint::new();
+        for (name, encode) in &checkpointed {
+            if let Some(value) = encode() {
+                checkpoint.insert(name.clone(), value);
+            }
+        }
+        // drop the entries for cells that are gone, and keep any registered while encoding
+        self.with_data(|data: &mut SodiumCtxData| {
+            let mut checkpointed = checkpointed;
+            checkpointed.retain(|(name, _)| checkpoint.get(name).is_some());
+            checkpointed.append(&mut data.checkpointed);
+            data.checkpointed = checkpointed;
+        });
+        checkpoint
+    }
+
+    pub fn restore(&self, checkpoint: &Checkpoint) {
+        self.with_data(|data: &mut SodiumCtxData| data.restored = checkpoint.clone());
+    }
+
+    pub fn restored(&self, name: &str) -> Option<Vec<u8>> {
+        self.with_data(|data: &mut SodiumCtxData| data.restored.get(name).map(|value: &[u8]| value.to_vec()))
+    }
+
+    // the restored value for name if there is one, otherwise the given default
+    pub fn restored_or<A,DECODE:FnOnce(&[u8])->Result<A,SodiumError>>(&self, name: &str, a: A, decode: DECODE) -> Result<A,SodiumError> {
+        match self.restored(name) {
+            Some(bytes) => decode(&bytes),
+            None => Ok(a)
+        }
+    }
+
+    pub fn set_panic_on_untracked_deps(&self, panic_on_untracked_deps: bool) {
+        self.with_data(|data: &mut SodiumCtxData| data.panic_on_untracked_deps = panic_on_untracked_deps);
+    }
+
     pub fn with_data<R,K:FnOnce(&mut SodiumCtxData)->R>(&self, k: K) -> R {
-        let mut l = self.data.lock();
-        let data: &mut SodiumCtxData = l.as_mut().unwrap();
+        let poison_policy = self.poison_policy();
+        let mut l = poison_policy::lock(&self.data, |_| poison_policy);
+        let data: &mut SodiumCtxData = &mut l;
         k(data)
     }
 
@@ -216,7 +650,7 @@ impl SodiumCtx {
         k(node_ref_count)
     }
 
-    pub fn end_of_transaction(&self) {
+    pub fn end_of_transaction(&self) -> Result<(),SodiumError> {
         self.with_data(|data: &mut SodiumCtxData| {
             data.transaction_depth = data.transaction_depth + 1;
             data.allow_collect_cycles_counter = data.allow_collect_cycles_counter + 1;
@@ -235,9 +669,13 @@ impl SodiumCtx {
                 self.update_node(node.node());
             }
         }
-        self.with_data(|data: &mut SodiumCtxData| {
-            data.transaction_depth = data.transaction_depth - 1;
-        });
+        // taken now, as transactions started from post have counters of their own
+        let stats =
+            self.with_data(|data: &mut SodiumCtxData| {
+                data.transaction_depth = data.transaction_depth - 1;
+                self.counters.stats(data.transaction_id)
+            });
+        self.run_hooks(|hooks: &TransactionHooks| &hooks.propagation_done, &stats);
         // pre_post
         let pre_post =
             self.with_data(|data: &mut SodiumCtxData| {
@@ -258,18 +696,30 @@ impl SodiumCtx {
         for mut k in post {
             k();
         }
+        self.run_hooks(|hooks: &TransactionHooks| &hooks.end, &stats);
         let allow_collect_cycles =
             self.with_data(|data: &mut SodiumCtxData| {
+                data.last_stats = stats.clone();
                 data.allow_collect_cycles_counter = data.allow_collect_cycles_counter - 1;
                 data.allow_collect_cycles_counter == 0
             });
         if allow_collect_cycles {
             // gc
-            self.collect_cycles()
+            self.collect_cycles_after(&stats)?;
         }
+        Ok(())
     }
 
+    // updates the dependencies of the node first, then the node if any of them changed, then its
+    // dependents
     pub fn update_node(&self, node: &Node) {
+        self._update_node(node, false);
+    }
+
+    // A node pulled in as a dependency of another leaves its dependents for later rather than
+    // updating them straight away, as the node that pulled it in has not updated yet and some of
+    // them may depend on it too.
+    fn _update_node(&self, node: &Node, pulled: bool) {
         let bail;
         {
             let mut visited = node.data.visited.write().unwrap();
@@ -300,7 +750,7 @@ impl SodiumCtx {
                 for dependency in &dependencies {
                     let visit_it = !*dependency.data().visited.read().unwrap();
                     if visit_it {
-                        _self.update_node(dependency.node());
+                        _self._update_node(dependency.node(), true);
                     }
                 }
             });
@@ -313,14 +763,37 @@ impl SodiumCtx {
                 .any(|node: &Box<dyn IsNode+Send+Sync+'static>| { *node.node().data().changed.read().unwrap() });
         // if dependencies changed, then execute update on current node
         if any_changed {
-            let mut update = node.data.update.write().unwrap();
+            let mut update = poison_policy::write(&node.data.update, self.poison_policy());
             let update: &mut Box<_> = &mut *update;
+            #[cfg(debug_assertions)]
+            let _updating_node_guard = UpdatingNodeGuard::new(node);
+            let start_op = if self.profiling.load(Ordering::Relaxed) { Some(Instant::now()) } else { None };
             update();
+            self.counters.nodes_updated.fetch_add(1, Ordering::Relaxed);
+            if let Some(start) = start_op {
+                let elapsed = start.elapsed();
+                self.with_profile(|profile: &mut HashMap<u32,NodeProfile>| {
+                    let profile =
+                        profile
+                            .entry(node.gc_node.id())
+                            .or_insert_with(|| NodeProfile { node_id: node.gc_node.id(), name: node.gc_node.name().to_string(), label: node.data.label.read().unwrap().clone(), updates: 0, total_time: Default::default() });
+                    profile.updates = profile.updates + 1;
+                    profile.total_time = profile.total_time + elapsed;
+                });
+            }
         }
         // if self changed then update dependents
         if *node.data.changed.read().unwrap() {
             let dependents = box_clone_vec_is_weak_node(&*node.data().dependents.read().unwrap());
-            {
+            if pulled {
+                self.with_data(|data: &mut SodiumCtxData| {
+                    for dependent in dependents {
+                        if let Some(dependent2) = dependent.upgrade() {
+                            data.changed_nodes.push(dependent2);
+                        }
+                    }
+                });
+            } else {
                 let _self = self.clone();
                 for dependent in dependents {
                     if let Some(dependent2) = dependent.upgrade() {
@@ -332,6 +805,134 @@ impl SodiumCtx {
     }
 
     pub fn collect_cycles(&self) {
-        self.gc_ctx.collect_cycles();
+        if let Err(err) = self.try_collect_cycles() {
+            panic!("{}", err);
+        }
+    }
+
+    // the gc hooks get the counters of the last transaction to end
+    pub fn try_collect_cycles(&self) -> Result<(),SodiumError> {
+        let stats = self.with_data(|data: &mut SodiumCtxData| data.last_stats.clone());
+        self.collect_cycles_after(&stats)
+    }
+
+    fn collect_cycles_after(&self, stats: &TransactionStats) -> Result<(),SodiumError> {
+        self.gc_ctx.try_collect_cycles()?;
+        self.run_hooks(|hooks: &TransactionHooks| &hooks.gc, stats);
+        Ok(())
+    }
+
+    pub fn count_listener_fired(&self) {
+        self.counters.listeners_fired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_sink_sent(&self) {
+        self.counters.sinks_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_profiling(&self, profiling: bool) {
+        self.profiling.store(profiling, Ordering::Relaxed);
+    }
+
+    // sorted by node name, then by label, then by node id
+    pub fn profile_report(&self) -> Vec<NodeProfile> {
+        let mut report: Vec<NodeProfile> = self.with_profile(|profile: &mut HashMap<u32,NodeProfile>| profile.values().cloned().collect());
+        report.sort_by(|a: &NodeProfile, b: &NodeProfile| a.name.cmp(&b.name).then(a.label.cmp(&b.label)).then(a.node_id.cmp(&b.node_id)));
+        report
+    }
+
+    pub fn clear_profile(&self) {
+        self.with_profile(|profile: &mut HashMap<u32,NodeProfile>| profile.clear());
+    }
+
+    // called as a node is dropped
+    pub fn forget_profile(&self, node_id: u32) {
+        self.with_profile(|profile: &mut HashMap<u32,NodeProfile>| profile.remove(&node_id));
+    }
+
+    fn with_profile<R,K:FnOnce(&mut HashMap<u32,NodeProfile>)->R>(&self, k: K) -> R {
+        let mut l = self.profile.lock();
+        let profile: &mut HashMap<u32,NodeProfile> = l.as_mut().unwrap();
+        k(profile)
+    }
+
+    pub fn set_async_spawner(&self, async_spawner: AsyncSpawner) {
+        self.with_data(|data: &mut SodiumCtxData| data.async_spawner_op = Some(async_spawner));
+    }
+
+    // panics if none has been set, as documented on Stream::map_async and Cell::map_async
+    pub fn async_spawner(&self) -> AsyncSpawner {
+        self.with_data(|data: &mut SodiumCtxData| data.async_spawner_op.clone())
+            .expect("map_async needs an async spawner, see SodiumCtx::set_async_spawner")
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.with_data(|data: &mut SodiumCtxData| data.transaction_depth > 0)
+    }
+
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        self.with_data(|data: &mut SodiumCtxData| data.timers.clock = clock);
+    }
+
+    pub fn now(&self) -> Duration {
+        let (clock, firing_op) = self.with_data(|data: &mut SodiumCtxData| (data.timers.clock.clone(), data.timers.firing_op));
+        firing_op.unwrap_or_else(|| clock.now())
+    }
+
+    pub fn schedule<K:FnOnce()+Send+'static>(&self, delay: Duration, k: K) {
+        let due = self.now() + delay;
+        self.schedule_at(due, k);
+    }
+
+    pub fn schedule_at<K:FnOnce()+Send+'static>(&self, due: Duration, k: K) {
+        self.with_data(|data: &mut SodiumCtxData| {
+            let seq = data.timers.next_seq;
+            data.timers.next_seq = seq + 1;
+            data.timers.pending.push(Timer { due, seq, action: Box::new(k) });
+        });
+    }
+
+    // Runs every timer that is due by the clock, earliest first, including ones scheduled by the
+    // timers it runs. Each runs outside of any transaction, so whatever it sends gets its own.
+    pub fn run_timers(&self) {
+        let clock = self.with_data(|data: &mut SodiumCtxData| data.timers.clock.clone());
+        let now = clock.now();
+        loop {
+            let timer_op =
+                self.with_data(|data: &mut SodiumCtxData| {
+                    let due = data.timers.pending.peek().map(|timer: &Timer| timer.due);
+                    let timer_op = match due {
+                        Some(due) if due <= now => data.timers.pending.pop(),
+                        _ => None
+                    };
+                    data.timers.firing_op = timer_op.as_ref().map(|timer: &Timer| timer.due);
+                    timer_op
+                });
+            match timer_op {
+                Some(timer) => (timer.action)(),
+                None => break
+            }
+        }
+    }
+
+    pub fn time_to_next_timer(&self) -> Option<Duration> {
+        let now = self.now();
+        self.with_data(|data: &mut SodiumCtxData| {
+            data.timers.pending
+                .peek()
+                .map(|timer: &Timer| timer.due.checked_sub(now).unwrap_or_default())
+        })
+    }
+
+    pub fn add_hook<SELECT:FnOnce(&mut TransactionHooks)->&mut Vec<TransactionHook>>(&self, select: SELECT, hook: TransactionHook) {
+        self.with_data(|data: &mut SodiumCtxData| select(&mut data.hooks).push(hook));
+    }
+
+    // the hooks are cloned out first, so they are free to use this context
+    fn run_hooks<SELECT:FnOnce(&TransactionHooks)->&Vec<TransactionHook>>(&self, select: SELECT, stats: &TransactionStats) {
+        let hooks = self.with_data(|data: &mut SodiumCtxData| select(&data.hooks).clone());
+        for hook in hooks {
+            hook(stats);
+        }
     }
 }
@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SodiumError {
+    // a CellLoop's cell was sampled before loop_ was called on it
+    CellLoopSampledBeforeLooped,
+    // loop_ was called more than once on the same StreamLoop or CellLoop
+    AlreadyLooped,
+    // a transaction ended leaving this many of the loops created inside it unlooped
+    NotLooped(usize),
+    // a lock was poisoned by a panic on another thread
+    Poisoned,
+    // the cycle collector found its reference counts in an inconsistent state
+    GcInvariant(String),
+    // a checkpoint could not be read, written or decoded
+    Checkpoint(String),
+    // a Recorder could not write or flush a recorded line
+    Record(String),
+    // a recorded trace could not be parsed or replayed
+    Replay(String),
+    // send_all was called inside a transaction, where its values could not each get a transaction of their own
+    SendAllInTransaction,
+    // a Lazy value's thunk ended up needing the value of the same Lazy
+    LazyCycle,
+    // TransactionGuard::abort was called on a transaction nested inside another one
+    AbortNested
+}
+
+impl fmt::Display for SodiumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SodiumError::CellLoopSampledBeforeLooped => write!(f, "CellLoop sampled before looped."),
+            SodiumError::AlreadyLooped => write!(f, "StreamLoop/CellLoop already looped."),
+            SodiumError::NotLooped(n) => write!(f, "{} StreamLoop/CellLoop(s) created in a transaction were not looped before it ended.", n),
+            SodiumError::Poisoned => write!(f, "lock poisoned by a panic on another thread."),
+            SodiumError::GcInvariant(msg) => write!(f, "{}", msg),
+            SodiumError::Checkpoint(msg) => write!(f, "checkpoint: {}", msg),
+            SodiumError::Record(msg) => write!(f, "record: {}", msg),
+            SodiumError::Replay(msg) => write!(f, "replay: {}", msg),
+            SodiumError::SendAllInTransaction => write!(f, "send_all called inside a transaction."),
+            SodiumError::LazyCycle => write!(f, "Lazy value depends on itself."),
+            SodiumError::AbortNested => write!(f, "only the outermost transaction can be aborted.")
+        }
+    }
+}
+
+impl Error for SodiumError {}
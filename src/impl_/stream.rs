@@ -1,19 +1,23 @@
 use crate::impl_::cell::Cell;
 use crate::impl_::dep::Dep;
 use crate::impl_::node::{Node, WeakNode, IsNode, IsWeakNode, box_clone_vec_is_node};
+use crate::impl_::poison_policy;
 use crate::impl_::lazy::Lazy;
 use crate::impl_::listener::Listener;
 use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::stream_loop::StreamLoop;
 use crate::impl_::stream_sink::StreamSink;
+use crate::impl_::stream_sink::WeakStreamSink;
 use crate::impl_::lambda::IsLambda1;
 use crate::impl_::lambda::IsLambda2;
 use crate::impl_::lambda::{lambda1, lambda1_deps, lambda2_deps};
 
+use std::future::Future;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::sync::Mutex;
 use std::sync::Weak;
+use std::time::Duration;
 
 pub struct StreamWeakForwardRef<A> {
     data: Arc<RwLock<Option<WeakStream<A>>>>
@@ -83,6 +87,55 @@ impl<A:Send+'static> IsWeakNode for WeakStream<A> {
     }
 }
 
+struct ThrottleData<A> {
+    waiting: bool,
+    pending_op: Option<A>
+}
+
+// at the end of the period fires the pending event and waits again, or stops waiting if there is none
+fn throttle_wait<A:Clone+Send+'static>(sodium_ctx: &SodiumCtx, period: Duration, throttle: &Arc<Mutex<ThrottleData<A>>>, ss: &WeakStreamSink<A>) {
+    let sodium_ctx2 = SodiumCtx::downgrade(sodium_ctx);
+    let throttle = throttle.clone();
+    let ss = ss.clone();
+    sodium_ctx.schedule(period, move || {
+        let a_op;
+        {
+            let mut l = throttle.lock();
+            let throttle: &mut ThrottleData<A> = l.as_mut().unwrap();
+            a_op = throttle.pending_op.take();
+            throttle.waiting = a_op.is_some();
+        }
+        if let Some(a) = a_op {
+            if let Some(ss2) = ss.upgrade() {
+                ss2.send(a);
+            }
+            if let Some(sodium_ctx2) = sodium_ctx2.upgrade() {
+                throttle_wait(&sodium_ctx2, period, &throttle, &ss);
+            }
+        }
+    });
+}
+
+impl Stream<()> {
+    // fires every period, starting one period from now
+    pub fn periodic(sodium_ctx: &SodiumCtx, period: Duration) -> Stream<()> {
+        let ticks: StreamSink<()> = StreamSink::new(sodium_ctx);
+        periodic_tick(sodium_ctx, sodium_ctx.now() + period, period, StreamSink::downgrade(&ticks));
+        ticks.stream()
+    }
+}
+
+// ticks stop once nothing is left listening to them
+fn periodic_tick(sodium_ctx: &SodiumCtx, due: Duration, period: Duration, ticks: WeakStreamSink<()>) {
+    let sodium_ctx2 = SodiumCtx::downgrade(sodium_ctx);
+    sodium_ctx.schedule_at(due, move || {
+        if let (Some(ticks2), Some(sodium_ctx2)) = (ticks.upgrade(), sodium_ctx2.upgrade()) {
+            ticks2.send(());
+            periodic_tick(&sodium_ctx2, due + period, period, ticks);
+        }
+    });
+}
+
 impl<A> Clone for Stream<A> {
     fn clone(&self) -> Self {
         Stream {
@@ -109,8 +162,8 @@ pub struct StreamData<A> {
 
 impl<A> Stream<A> {
     pub fn with_data<R,K:FnOnce(&mut StreamData<A>)->R>(&self, k: K) -> R {
-        let mut l = self.data.lock();
-        let data: &mut StreamData<A> = l.as_mut().unwrap();
+        let mut l = poison_policy::lock(&self.data, |data: &StreamData<A>| data.sodium_ctx.poison_policy());
+        let data: &mut StreamData<A> = &mut l;
         k(data)
     }
 
@@ -280,6 +333,8 @@ impl<A:Send+'static> Stream<A> {
         let s2 = s2.clone();
         let s2_node = s2.box_clone();
         let s2_dep = s2.to_dep();
+        // locking the firing value of both sides would deadlock when a stream is merged with itself
+        let same_stream = Arc::ptr_eq(&self.data, &s2.data);
         let sodium_ctx = self.sodium_ctx().clone();
         Stream::_new(
             &sodium_ctx,
@@ -289,6 +344,13 @@ impl<A:Send+'static> Stream<A> {
                     &sodium_ctx,
                     "Stream::merge",
                     move || {
+                        if same_stream {
+                            let firing_op = self_.with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                            if let Some(firing) = firing_op {
+                                s.unwrap()._send(f.call(&firing, &firing));
+                            }
+                            return;
+                        }
                         self_.with_firing_op(|firing1_op: &mut Option<A>| {
                             s2.with_firing_op(|firing2_op: &mut Option<A>| {
                                 if let Some(ref firing1) = firing1_op {
@@ -361,6 +423,99 @@ impl<A:Send+'static> Stream<A> {
         })
     }
 
+    // Cell::switch_s over a hold of the streams f makes for each event, so only the latest one is
+    // listened to and the ones it replaced can be collected.
+    pub fn switch_map<B,FN>(&self, f: FN) -> Stream<B>
+        where A: Clone,
+              B: Send + Clone + 'static,
+              FN: IsLambda1<A,Stream<B>> + Send + Sync + 'static
+    {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let csb = self.map(f).hold(Stream::new(&sodium_ctx));
+            Cell::switch_s(&csb)
+        })
+    }
+
+    // Listens to every stream f has made so far, each one staying a dependency of this node from
+    // the transaction after it was made until the result is unlistened. Values fired by several of
+    // them at once are combined with merge in the order they were made. The list of streams is
+    // only ever appended to, so an event costs one call to f however many came before it, where
+    // switch_s_keyed would compare the whole map of streams on every change.
+    pub fn merge_map<B,FN,MERGE>(&self, mut f: FN, mut merge: MERGE) -> Stream<B>
+        where A: Clone,
+              B: Send + Clone + 'static,
+              FN: IsLambda1<A,Stream<B>> + Send + Sync + 'static,
+              MERGE: IsLambda2<B,B,B> + Send + Sync + 'static
+    {
+        let self_ = self.clone();
+        let sodium_ctx = self.sodium_ctx();
+        Stream::_new(
+            &sodium_ctx,
+            |sb: StreamWeakForwardRef<B>| {
+                let f_deps = lambda1_deps(&f);
+                let merge_deps = lambda2_deps(&merge);
+                // the node's dependencies are what keep the streams alive, the closure only refers to
+                // them weakly so it holds nothing the cycle collector can't see
+                let inner: Arc<Mutex<Vec<WeakStream<B>>>> = Arc::new(Mutex::new(Vec::new()));
+                let node = Node::new(
+                    &sodium_ctx,
+                    "Stream::merge_map",
+                    || {},
+                    vec![self.box_clone()]
+                );
+                let node_update;
+                {
+                    let sodium_ctx = sodium_ctx.clone();
+                    let node = Node::downgrade2(&node);
+                    node_update = move || {
+                        let mut out_op: Option<B> = None;
+                        {
+                            let l = inner.lock();
+                            let inner: &Vec<WeakStream<B>> = l.as_ref().unwrap();
+                            for s in inner {
+                                let s = s.upgrade().unwrap();
+                                s.with_firing_op(|firing_op: &mut Option<B>| {
+                                    if let Some(ref firing) = firing_op {
+                                        out_op = Some(match out_op.take() {
+                                            Some(b) => merge.call(&b, firing),
+                                            None => firing.clone()
+                                        });
+                                    }
+                                });
+                            }
+                        }
+                        // f is called once the firing value is out of self_, as the stream it makes
+                        // may itself read self_
+                        let firing_op = self_.with_firing_op(|firing_op: &mut Option<A>| firing_op.clone());
+                        let new_inner_op = firing_op.map(|a: A| f.call(&a));
+                        if let Some(new_inner) = new_inner_op {
+                            let node = node.clone();
+                            let inner = inner.clone();
+                            sodium_ctx.pre_post(move || {
+                                if let Some(node) = node.upgrade2() {
+                                    IsNode::add_dependency(&node, new_inner.clone());
+                                    inner.lock().as_mut().unwrap().push(Stream::downgrade(&new_inner));
+                                }
+                            });
+                        }
+                        if let Some(out) = out_op {
+                            sb.unwrap()._send(out);
+                        }
+                    };
+                }
+                IsNode::add_update_dependencies(&node, f_deps);
+                IsNode::add_update_dependencies(&node, merge_deps);
+                IsNode::add_update_dependencies(&node, vec![self.to_dep()]);
+                {
+                    let mut update = node.data.update.write().unwrap();
+                    *update = Box::new(node_update);
+                }
+                node
+            }
+        )
+    }
+
     pub fn defer(&self) -> Stream<A> where A: Clone {
         let sodium_ctx = self.sodium_ctx();
         sodium_ctx.transaction(|| {
@@ -369,15 +524,175 @@ impl<A:Send+'static> Stream<A> {
             let sodium_ctx = sodium_ctx.clone();
             let ss = StreamSink::downgrade(&ss);
             let listener = self.listen_weak(move |a:&A| {
-                let ss = ss.upgrade().unwrap();
-                let a = a.clone();
-                sodium_ctx.post(move || ss.send(a.clone()))
+                // the deferred stream may already be gone, in which case there is nobody to send to
+                if let Some(ss) = ss.upgrade() {
+                    let a = a.clone();
+                    sodium_ctx.post(move || ss.send(a.clone()))
+                }
             });
             IsNode::add_keep_alive(&s, &listener.gc_node);
             return s;
         })
     }
 
+    // The timer operators below all work like defer, listening weakly and sending to a sink in
+    // a transaction of its own once the timer runs, see SodiumCtx::run_timers.
+
+    pub fn delay(&self, period: Duration) -> Stream<A> where A: Clone {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let ss = StreamSink::new(&sodium_ctx);
+            let s = ss.stream();
+            let sodium_ctx = sodium_ctx.clone();
+            let ss = StreamSink::downgrade(&ss);
+            let listener = self.listen_weak(move |a:&A| {
+                let ss = ss.clone();
+                let a = a.clone();
+                sodium_ctx.schedule(period, move || {
+                    if let Some(ss) = ss.upgrade() {
+                        ss.send(a);
+                    }
+                });
+            });
+            IsNode::add_keep_alive(&s, &listener.gc_node);
+            s
+        })
+    }
+
+    // Runs the future f makes for each event on the context's async spawner, and fires its result in a
+    // transaction of its own. A result is dropped if a later event arrived while its future ran.
+    pub fn map_async<B,FUT,FN>(&self, mut f: FN) -> Stream<B>
+        where B: Send + Clone + 'static,
+              FUT: Future<Output=B> + Send + 'static,
+              FN: IsLambda1<A,FUT> + Send + Sync + 'static
+    {
+        let sodium_ctx = self.sodium_ctx();
+        let async_spawner = sodium_ctx.async_spawner();
+        sodium_ctx.transaction(|| {
+            // the futures are not Clone, so each is taken out by the one listener below
+            let f_deps = lambda1_deps(&f);
+            let futures = self.map(lambda1(move |a: &A| Arc::new(Mutex::new(Some(f.call(a)))), f_deps));
+            let ss = StreamSink::new(&sodium_ctx);
+            let s = ss.stream();
+            let sodium_ctx = sodium_ctx.clone();
+            let ss = StreamSink::downgrade(&ss);
+            let latest: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+            let listener = futures.listen_weak(move |future: &Arc<Mutex<Option<FUT>>>| {
+                let future_op = future.lock().as_mut().unwrap().take();
+                if let Some(future) = future_op {
+                    let generation;
+                    {
+                        let mut l = latest.lock();
+                        let latest: &mut u64 = l.as_mut().unwrap();
+                        *latest += 1;
+                        generation = *latest;
+                    }
+                    let sodium_ctx = sodium_ctx.clone();
+                    let ss = ss.clone();
+                    let latest = latest.clone();
+                    async_spawner(Box::pin(async move {
+                        let b = future.await;
+                        // checked in the sending transaction, so a later event can't slip in between
+                        let sodium_ctx2 = sodium_ctx.clone();
+                        let send = move || {
+                            if let Some(ss) = ss.upgrade() {
+                                sodium_ctx2.transaction(|| {
+                                    if *latest.lock().unwrap() == generation {
+                                        ss.send(b.clone());
+                                    }
+                                });
+                            }
+                        };
+                        // a spawner that runs the future straight away would otherwise send inside the current transaction
+                        if sodium_ctx.in_transaction() {
+                            sodium_ctx.post(send);
+                        } else {
+                            send();
+                        }
+                    }));
+                }
+            });
+            IsNode::add_keep_alive(&s, &listener.gc_node);
+            s
+        })
+    }
+
+    // fires the latest event once none has followed it for the period
+    pub fn debounce(&self, period: Duration) -> Stream<A> where A: Clone {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let ss = StreamSink::new(&sodium_ctx);
+            let s = ss.stream();
+            let sodium_ctx = sodium_ctx.clone();
+            let ss = StreamSink::downgrade(&ss);
+            // the latest event, and a count of events so a timer can tell whether it is still the latest
+            let latest: Arc<Mutex<(u64,Option<A>)>> = Arc::new(Mutex::new((0, None)));
+            let listener = self.listen_weak(move |a:&A| {
+                let generation;
+                {
+                    let mut l = latest.lock();
+                    let latest: &mut (u64,Option<A>) = l.as_mut().unwrap();
+                    latest.0 += 1;
+                    latest.1 = Some(a.clone());
+                    generation = latest.0;
+                }
+                let ss = ss.clone();
+                let latest = latest.clone();
+                sodium_ctx.schedule(period, move || {
+                    let a_op;
+                    {
+                        let mut l = latest.lock();
+                        let latest: &mut (u64,Option<A>) = l.as_mut().unwrap();
+                        a_op = if latest.0 == generation { latest.1.take() } else { None };
+                    }
+                    if let (Some(a), Some(ss)) = (a_op, ss.upgrade()) {
+                        ss.send(a);
+                    }
+                });
+            });
+            IsNode::add_keep_alive(&s, &listener.gc_node);
+            s
+        })
+    }
+
+    // Fires an event straight away, then at most once per period after that. The latest of the
+    // events that arrive while it waits is fired when the period is up.
+    pub fn throttle(&self, period: Duration) -> Stream<A> where A: Clone {
+        let sodium_ctx = self.sodium_ctx();
+        sodium_ctx.transaction(|| {
+            let ss = StreamSink::new(&sodium_ctx);
+            let s = ss.stream();
+            let sodium_ctx = sodium_ctx.clone();
+            let ss = StreamSink::downgrade(&ss);
+            let throttle = Arc::new(Mutex::new(ThrottleData { waiting: false, pending_op: None }));
+            let listener = self.listen_weak(move |a:&A| {
+                let waiting;
+                {
+                    let mut l = throttle.lock();
+                    let throttle: &mut ThrottleData<A> = l.as_mut().unwrap();
+                    waiting = throttle.waiting;
+                    if waiting {
+                        throttle.pending_op = Some(a.clone());
+                    } else {
+                        throttle.waiting = true;
+                    }
+                }
+                if !waiting {
+                    let ss2 = ss.clone();
+                    let a = a.clone();
+                    sodium_ctx.post(move || {
+                        if let Some(ss) = ss2.upgrade() {
+                            ss.send(a.clone());
+                        }
+                    });
+                    throttle_wait(&sodium_ctx, period, &throttle, &ss);
+                }
+            });
+            IsNode::add_keep_alive(&s, &listener.gc_node);
+            s
+        })
+    }
+
     pub fn once(&self) -> Stream<A> where A: Clone {
         let self_ = self.clone();
         let sodium_ctx = self.sodium_ctx().clone();
@@ -416,18 +731,34 @@ impl<A:Send+'static> Stream<A> {
         )
     }
 
+    pub fn add_cleanup<CLEANUP:FnOnce()+Send+Sync+'static>(&self, cleanup: CLEANUP) -> Stream<A> {
+        IsNode::add_cleanup(self.node(), cleanup);
+        self.clone()
+    }
+
+    pub fn named(&self, label: &str) -> Stream<A> {
+        self.node().set_label(label);
+        self.clone()
+    }
+
     pub fn _listen<K:IsLambda1<A,()>+Send+Sync+'static>(&self, mut k: K, weak: bool) -> Listener {
         let self_ = self.clone();
+        let sodium_ctx = self.sodium_ctx();
         let node =
             Node::new(
                 &self.sodium_ctx(),
                 "Stream::listen",
                 move || {
-                    self_.with_data(|data: &mut StreamData<A>| {
-                        for firing in &data.firing_op {
-                            k.call(firing)
-                        }
-                    });
+                    let fired =
+                        self_.with_data(|data: &mut StreamData<A>| {
+                            for firing in &data.firing_op {
+                                k.call(firing)
+                            }
+                            data.firing_op.is_some()
+                        });
+                    if fired {
+                        sodium_ctx.count_listener_fired();
+                    }
                 },
                 vec![self.box_clone()]
             );
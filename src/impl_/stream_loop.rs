@@ -1,6 +1,7 @@
 use crate::impl_::gc_node::{GcNode, Tracer};
 use crate::impl_::node::IsNode;
-use crate::impl_::sodium_ctx::SodiumCtx;
+use crate::impl_::sodium_ctx::{SodiumCtx, SodiumCtxData};
+use crate::impl_::sodium_error::SodiumError;
 use crate::impl_::stream::Stream;
 
 use std::sync::Arc;
@@ -68,9 +69,15 @@ impl<A:Clone+Send+'static> StreamLoop<A> {
                 tracer(stream_loop_data.stream.gc_node());
             };
         }
+        let gc_node = GcNode::new(&sodium_ctx.gc_ctx(), "StreamLoop::new", gc_node_destructor, gc_node_trace);
+        sodium_ctx.with_data(|data: &mut SodiumCtxData| {
+            if data.transaction_depth > 0 {
+                data.unlooped.push(gc_node.clone());
+            }
+        });
         StreamLoop {
             data: stream_loop_data,
-            gc_node: GcNode::new(&sodium_ctx.gc_ctx(), "StreamLoop::new", gc_node_destructor, gc_node_trace)
+            gc_node
         }
     }
 
@@ -79,11 +86,21 @@ impl<A:Clone+Send+'static> StreamLoop<A> {
     }
 
     pub fn loop_(&self, s: &Stream<A>) {
+        if let Err(err) = self.try_loop(s) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_loop(&self, s: &Stream<A>) -> Result<(),SodiumError> {
         self.with_data(|data: &mut StreamLoopData<A>| {
             if data.looped {
-                panic!("StreamLoop already looped.");
+                return Err(SodiumError::AlreadyLooped);
             }
             data.looped = true;
+            let gc_node = &self.gc_node;
+            data.stream.sodium_ctx().with_data(|data: &mut SodiumCtxData| {
+                data.unlooped.retain(|gc_node2: &GcNode| !gc_node2.ptr_eq(gc_node));
+            });
             IsNode::add_dependency(&data.stream, s.clone());
             IsNode::add_update_dependencies(&data.stream, vec![s.to_dep()]);
             {
@@ -98,6 +115,7 @@ impl<A:Clone+Send+'static> StreamLoop<A> {
                     });
                 });
             }
+            Ok(())
         })
     }
 
@@ -1,8 +1,10 @@
 use crate::impl_::node::IsNode;
 use crate::impl_::stream::Stream;
+use crate::impl_::stream::StreamData;
 use crate::impl_::stream::WeakStream;
 use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::sodium_ctx::SodiumCtxData;
+use crate::impl_::sodium_error::SodiumError;
 
 pub struct StreamSink<A> {
     stream: Stream<A>,
@@ -10,8 +12,7 @@ pub struct StreamSink<A> {
 }
 
 pub struct WeakStreamSink<A> {
-    stream: WeakStream<A>,
-    sodium_ctx: SodiumCtx
+    stream: WeakStream<A>
 }
 
 impl<A> Clone for StreamSink<A> {
@@ -23,6 +24,14 @@ impl<A> Clone for StreamSink<A> {
     }
 }
 
+impl<A> Clone for WeakStreamSink<A> {
+    fn clone(&self) -> Self {
+        WeakStreamSink {
+            stream: self.stream.clone()
+        }
+    }
+}
+
 impl<A:Send+'static> StreamSink<A> {
     pub fn new(sodium_ctx: &SodiumCtx) -> StreamSink<A> {
         StreamSink {
@@ -43,30 +52,60 @@ impl<A:Send+'static> StreamSink<A> {
     }
 
     pub fn send(&self, a: A) {
-        self.sodium_ctx.transaction(|| {
+        if let Err(err) = self.try_send(a) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_send(&self, a: A) -> Result<(),SodiumError> {
+        self.sodium_ctx.try_transaction(|| {
+            #[cfg(debug_assertions)]
+            {
+                let sent_twice = self.stream.with_data(|data: &mut StreamData<A>| data.coalescer_op.is_none() && data.firing_op.is_some());
+                if sent_twice {
+                    panic!("send called more than once in a transaction on a sink without a coalescer, only the last value would be kept. Use new_with_coalescer to combine the values.");
+                }
+            }
             let node = self.stream();
             {
                 let mut changed = node.data().changed.write().unwrap();
                 *changed = true;
             }
-            self.sodium_ctx.with_data(|data: &mut SodiumCtxData| {
-                data.changed_nodes.push(node.box_clone());
-            });
+            self.sodium_ctx.with_data(|data: &mut SodiumCtxData| data.changed_nodes.push(node.box_clone()));
+            self.sodium_ctx.count_sink_sent();
             self.stream._send(a);
-        });
+        })
+    }
+
+    pub fn send_all<I:IntoIterator<Item=A>>(&self, as_: I) {
+        if let Err(err) = self.try_send_all(as_) {
+            panic!("{}", err);
+        }
+    }
+
+    pub fn try_send_all<I:IntoIterator<Item=A>>(&self, as_: I) -> Result<(),SodiumError> {
+        self.sodium_ctx.try_batch(|| {
+            // the batch holds the transaction lock by now, so an open transaction is this thread's
+            if self.sodium_ctx.in_transaction() {
+                return Err(SodiumError::SendAllInTransaction);
+            }
+            for a in as_ {
+                self.try_send(a)?;
+            }
+            Ok(())
+        })
     }
 
     pub fn downgrade(this: &Self) -> WeakStreamSink<A> {
         WeakStreamSink {
-            stream: Stream::downgrade(&this.stream),
-            sodium_ctx: this.sodium_ctx.clone()
+            stream: Stream::downgrade(&this.stream)
         }
     }
 }
 
 impl<A> WeakStreamSink<A> {
     pub fn upgrade(&self) -> Option<StreamSink<A>> {
-        let sodium_ctx = self.sodium_ctx.clone();
-        self.stream.upgrade().map(|stream: Stream<A>| StreamSink { stream, sodium_ctx })
+        // the context comes from the stream, see WeakNode
+        self.stream.upgrade().map(|stream: Stream<A>| StreamSink { sodium_ctx: stream.sodium_ctx(), stream })
     }
 }
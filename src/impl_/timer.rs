@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+// Where the timer operators get the time from. Times are measured from whatever fixed point the
+// clock picks, only the differences between them matter.
+pub trait Clock: Send+Sync {
+    fn now(&self) -> Duration;
+}
+
+// wall clock time since the clock was created
+pub struct SystemClock {
+    start: Instant
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// A clock that only moves when told to, for tests. Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock { now: Arc::new(Mutex::new(Duration::from_secs(0))) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut l = self.now.lock();
+        let now: &mut Duration = l.as_mut().unwrap();
+        *now += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+pub struct Timer {
+    pub due: Duration,
+    // keeps timers due at the same time in the order they were scheduled
+    pub seq: u64,
+    pub action: Box<dyn FnOnce()+Send>
+}
+
+// Ordered by (due, seq) reversed, so the top of a BinaryHeap is the timer that runs next.
+impl Ord for Timer {
+    fn cmp(&self, other: &Timer) -> Ordering {
+        (other.due, other.seq).cmp(&(self.due, self.seq))
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Timer) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Timer) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for Timer {}
+
+pub struct Timers {
+    pub clock: Arc<dyn Clock>,
+    pub next_seq: u64,
+    pub pending: BinaryHeap<Timer>,
+    // the due time of the timer being run, which is what now() reports while it runs
+    pub firing_op: Option<Duration>
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers {
+            clock: Arc::new(SystemClock::new()),
+            next_seq: 0,
+            pending: BinaryHeap::new(),
+            firing_op: None
+        }
+    }
+}
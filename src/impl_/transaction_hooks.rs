@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+// What happened in one outermost transaction. Nodes updated includes the nodes behind listeners.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionStats {
+    pub transaction_id: u64,
+    pub nodes_updated: u64,
+    pub listeners_fired: u64,
+    pub sinks_sent: u64
+}
+
+// The counters of the current outermost transaction, bumped for every node update, listener fired
+// and send, so kept in atomics rather than behind the context lock.
+#[derive(Default)]
+pub struct TransactionCounters {
+    pub nodes_updated: AtomicU64,
+    pub listeners_fired: AtomicU64,
+    pub sinks_sent: AtomicU64
+}
+
+impl TransactionCounters {
+    pub fn reset(&self) {
+        self.nodes_updated.store(0, Ordering::Relaxed);
+        self.listeners_fired.store(0, Ordering::Relaxed);
+        self.sinks_sent.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, transaction_id: u64) -> TransactionStats {
+        TransactionStats {
+            transaction_id,
+            nodes_updated: self.nodes_updated.load(Ordering::Relaxed),
+            listeners_fired: self.listeners_fired.load(Ordering::Relaxed),
+            sinks_sent: self.sinks_sent.load(Ordering::Relaxed)
+        }
+    }
+}
+
+pub type TransactionHook = Arc<dyn Fn(&TransactionStats)+Send+Sync>;
+
+#[derive(Clone, Default)]
+pub struct TransactionHooks {
+    pub start: Vec<TransactionHook>,
+    pub propagation_done: Vec<TransactionHook>,
+    pub end: Vec<TransactionHook>,
+    pub gc: Vec<TransactionHook>
+}
@@ -0,0 +1,66 @@
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::ThreadId;
+
+// Lets one thread at a time run transactions, as Java Sodium's transaction lock does. The thread
+// holding it can take it again, for nested transactions and for the transactions of a send_all
+// batch, which holds it from its first value to its last.
+pub struct TransactionLock {
+    // the holding thread and how many times it has taken the lock
+    owner: Mutex<Option<(ThreadId,u32)>>,
+    released: Condvar
+}
+
+impl TransactionLock {
+    pub fn new() -> TransactionLock {
+        TransactionLock {
+            owner: Mutex::new(None),
+            released: Condvar::new()
+        }
+    }
+
+    pub fn acquire(&self) {
+        let this_thread = thread::current().id();
+        let mut l = self.owner.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            match *l {
+                None => {
+                    *l = Some((this_thread, 1));
+                    return;
+                },
+                Some((owner, ref mut count)) if owner == this_thread => {
+                    *count += 1;
+                    return;
+                },
+                Some(_) => l = self.released.wait(l).unwrap_or_else(|err| err.into_inner())
+            }
+        }
+    }
+
+    // does nothing on a thread that doesn't hold the lock, which release_all may have let go of
+    pub fn release(&self) {
+        let this_thread = thread::current().id();
+        let mut l = self.owner.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((owner, ref mut count)) = *l {
+            if owner != this_thread {
+                return;
+            }
+            *count -= 1;
+            if *count == 0 {
+                *l = None;
+                self.released.notify_all();
+            }
+        }
+    }
+
+    // for a panic unwinding out of a transaction, which leaves no transaction open on this thread
+    pub fn release_all(&self) {
+        let this_thread = thread::current().id();
+        let mut l = self.owner.lock().unwrap_or_else(|err| err.into_inner());
+        if matches!(*l, Some((owner, _)) if owner == this_thread) {
+            *l = None;
+            self.released.notify_all();
+        }
+    }
+}
@@ -4,17 +4,33 @@ extern crate log;
 mod impl_;
 mod cell;
 mod cell_loop;
+mod cell_map;
 mod cell_sink;
+mod cell_vec;
+mod either;
 mod listener;
 mod operational;
+mod recorder;
 mod sodium_ctx;
 mod stream;
 mod stream_loop;
 mod stream_sink;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use self::cell::Cell;
 pub use self::cell_loop::CellLoop;
+pub use self::cell_map::CellMap;
+pub use self::cell_map::CellMapSink;
+pub use self::cell_map::MapDiff;
+pub use self::cell_map::SharedMap;
 pub use self::cell_sink::CellSink;
+pub use self::cell_vec::CellVec;
+pub use self::cell_vec::CellVecSink;
+pub use self::cell_vec::SharedVec;
+pub use self::cell_vec::VecDiff;
+pub use self::either::Either;
+pub use self::impl_::checkpoint::Checkpoint;
 pub use self::impl_::dep::Dep;
 pub use self::impl_::lambda::IsLambda1;
 pub use self::impl_::lambda::IsLambda2;
@@ -22,17 +38,44 @@ pub use self::impl_::lambda::IsLambda3;
 pub use self::impl_::lambda::IsLambda4;
 pub use self::impl_::lambda::IsLambda5;
 pub use self::impl_::lambda::IsLambda6;
+pub use self::impl_::lambda::IsLambda7;
+pub use self::impl_::lambda::IsLambda8;
+pub use self::impl_::lambda::IsLambda9;
+pub use self::impl_::lambda::IsLambda10;
+pub use self::impl_::lambda::IsLambda11;
+pub use self::impl_::lambda::IsLambda12;
 pub use self::impl_::lambda::Lambda;
+pub use self::impl_::lambda::Traceable;
 pub use self::impl_::lambda::lambda1;
 pub use self::impl_::lambda::lambda2;
 pub use self::impl_::lambda::lambda3;
 pub use self::impl_::lambda::lambda4;
 pub use self::impl_::lambda::lambda5;
 pub use self::impl_::lambda::lambda6;
+pub use self::impl_::lambda::lambda7;
+pub use self::impl_::lambda::lambda8;
+pub use self::impl_::lambda::lambda9;
+pub use self::impl_::lambda::lambda10;
+pub use self::impl_::lambda::lambda11;
+pub use self::impl_::lambda::lambda12;
 pub use self::impl_::lazy::Lazy;
 pub use self::impl_::node::Node;
+pub use self::impl_::node_profile::NodeProfile;
+pub use self::impl_::poison_policy::PoisonPolicy;
+pub use self::impl_::sodium_ctx::TransactionGuard;
+pub use self::impl_::sodium_error::SodiumError;
+pub use self::impl_::timer::Clock;
+pub use self::impl_::timer::ManualClock;
+pub use self::impl_::timer::SystemClock;
+pub use self::impl_::transaction_hooks::TransactionStats;
 pub use self::listener::Listener;
+pub use self::listener::ListenerGuard;
+pub use self::listener::Listeners;
 pub use self::operational::Operational;
+pub use self::recorder::RecordedCellSink;
+pub use self::recorder::RecordedStreamSink;
+pub use self::recorder::Recorder;
+pub use self::recorder::Replayer;
 pub use self::sodium_ctx::SodiumCtx;
 pub use self::stream::Stream;
 pub use self::stream_loop::StreamLoop;
@@ -1,4 +1,5 @@
 use crate::impl_::listener::Listener as ListenerImpl;
+use crate::SodiumCtx;
 
 pub struct Listener {
     pub impl_: ListenerImpl
@@ -8,4 +9,83 @@ impl Listener {
     pub fn unlisten(&self) {
         self.impl_.unlisten();
     }
+
+    // a single listener that unlistens both self and other
+    pub fn append(&self, other: &Listener) -> Listener {
+        Listener { impl_: self.impl_.append(&other.impl_) }
+    }
+
+    // a single listener that unlistens all of the given listeners
+    pub fn combine(sodium_ctx: &SodiumCtx, listeners: Vec<Listener>) -> Listener {
+        Listener {
+            impl_: ListenerImpl::new_combined(&sodium_ctx.impl_, listeners.into_iter().map(|l: Listener| l.impl_).collect())
+        }
+    }
+
+    // unlistens automatically when the returned guard is dropped
+    pub fn into_guard(self) -> ListenerGuard {
+        ListenerGuard { listener: self }
+    }
+}
+
+pub struct ListenerGuard {
+    listener: Listener
+}
+
+impl ListenerGuard {
+    pub fn new(listener: Listener) -> ListenerGuard {
+        ListenerGuard { listener }
+    }
+
+    pub fn listener(&self) -> &Listener {
+        &self.listener
+    }
+}
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        self.listener.unlisten();
+    }
+}
+
+// A collection of listeners that are unlistened together, on unlisten or when it is dropped, like ListenerGuard.
+pub struct Listeners {
+    listeners: Vec<Listener>
+}
+
+impl Listeners {
+    pub fn new() -> Listeners {
+        Listeners { listeners: Vec::new() }
+    }
+
+    pub fn push(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    // unlistens every listener held and empties the collection
+    pub fn unlisten(&mut self) {
+        for listener in self.listeners.drain(..) {
+            listener.unlisten();
+        }
+    }
+}
+
+impl Default for Listeners {
+    fn default() -> Listeners {
+        Listeners::new()
+    }
+}
+
+impl Drop for Listeners {
+    fn drop(&mut self) {
+        self.unlisten();
+    }
 }
@@ -0,0 +1,304 @@
+use crate::Cell;
+use crate::CellSink;
+use crate::SodiumCtx;
+use crate::SodiumError;
+use crate::Stream;
+use crate::StreamSink;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// Logs every send made through the sinks it wraps, one line per send:
+//
+//     <transaction>\t<sequence>\t<sink name>\t<encoded value>
+//
+// Sends made in the same transaction share a transaction number, the sequence number counts
+// every send the recorder has seen. Backslashes, tabs and newlines in names and encoded values
+// are escaped, so codecs are free to produce any text.
+pub struct Recorder {
+    sodium_ctx: SodiumCtx,
+    data: Arc<Mutex<RecorderData>>
+}
+
+struct RecorderData {
+    next_sequence: u64,
+    lines: Vec<String>,
+    writer_op: Option<Box<dyn Write+Send>>,
+    error_op: Option<SodiumError>
+}
+
+impl Clone for Recorder {
+    fn clone(&self) -> Self {
+        Recorder {
+            sodium_ctx: SodiumCtx { impl_: self.sodium_ctx.impl_.clone() },
+            data: self.data.clone()
+        }
+    }
+}
+
+impl Recorder {
+    // keeps the recorded lines in memory, see lines
+    pub fn new(sodium_ctx: &SodiumCtx) -> Recorder {
+        Recorder::_new(sodium_ctx, None)
+    }
+
+    // writes each line out as it is recorded instead of keeping it
+    pub fn with_writer<W:Write+Send+'static>(sodium_ctx: &SodiumCtx, writer: W) -> Recorder {
+        Recorder::_new(sodium_ctx, Some(Box::new(writer)))
+    }
+
+    fn _new(sodium_ctx: &SodiumCtx, writer_op: Option<Box<dyn Write+Send>>) -> Recorder {
+        Recorder {
+            sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+            data: Arc::new(Mutex::new(RecorderData {
+                next_sequence: 0,
+                lines: Vec::new(),
+                writer_op,
+                error_op: None
+            }))
+        }
+    }
+
+    pub fn stream_sink<A:Clone+Send+'static,ENCODE:Fn(&A)->String+Send+Sync+'static>(&self, name: &str, sink: &StreamSink<A>, encode: ENCODE) -> RecordedStreamSink<A> {
+        RecordedStreamSink {
+            name: name.to_string(),
+            sink: sink.clone(),
+            recorder: self.clone(),
+            encode: Arc::new(encode)
+        }
+    }
+
+    pub fn cell_sink<A:Clone+Send+'static,ENCODE:Fn(&A)->String+Send+Sync+'static>(&self, name: &str, sink: &CellSink<A>, encode: ENCODE) -> RecordedCellSink<A> {
+        RecordedCellSink {
+            name: name.to_string(),
+            sink: sink.clone(),
+            recorder: self.clone(),
+            encode: Arc::new(encode)
+        }
+    }
+
+    // the recorded lines, empty when recording to a writer
+    pub fn lines(&self) -> Vec<String> {
+        self.with_data(|data: &mut RecorderData| data.lines.clone())
+    }
+
+    // flushes the writer, and reports the first write error if any line failed to be written
+    pub fn flush(&self) -> Result<(),SodiumError> {
+        self.with_data(|data: &mut RecorderData| {
+            if let Some(err) = data.error_op.take() {
+                return Err(err);
+            }
+            if let Some(ref mut writer) = data.writer_op {
+                writer.flush().map_err(|err| SodiumError::Record(err.to_string()))?;
+            }
+            Ok(())
+        })
+    }
+
+    // must be called inside the transaction the send is made in
+    fn record(&self, name: &str, value: String) {
+        let transaction_id = self.sodium_ctx.impl_.transaction_id();
+        self.with_data(|data: &mut RecorderData| {
+            let line = format!("{}\t{}\t{}\t{}", transaction_id, data.next_sequence, escape(name), escape(&value));
+            data.next_sequence += 1;
+            match data.writer_op {
+                Some(ref mut writer) => {
+                    if let Err(err) = writeln!(writer, "{}", line) {
+                        if data.error_op.is_none() {
+                            data.error_op = Some(SodiumError::Record(err.to_string()));
+                        }
+                    }
+                },
+                None => data.lines.push(line)
+            }
+        });
+    }
+
+    fn with_data<R,K:FnOnce(&mut RecorderData)->R>(&self, k: K) -> R {
+        let mut l = self.data.lock();
+        let data: &mut RecorderData = l.as_mut().unwrap();
+        k(data)
+    }
+}
+
+pub struct RecordedStreamSink<A> {
+    name: String,
+    sink: StreamSink<A>,
+    recorder: Recorder,
+    encode: Arc<dyn Fn(&A)->String+Send+Sync>
+}
+
+impl<A> Clone for RecordedStreamSink<A> {
+    fn clone(&self) -> Self {
+        RecordedStreamSink {
+            name: self.name.clone(),
+            sink: self.sink.clone(),
+            recorder: self.recorder.clone(),
+            encode: self.encode.clone()
+        }
+    }
+}
+
+impl<A:Clone+Send+'static> RecordedStreamSink<A> {
+    pub fn stream(&self) -> Stream<A> {
+        self.sink.stream()
+    }
+
+    pub fn send(&self, a: A) {
+        self.recorder.sodium_ctx.transaction(|| {
+            self.recorder.record(&self.name, (self.encode)(&a));
+            self.sink.send(a);
+        });
+    }
+}
+
+pub struct RecordedCellSink<A> {
+    name: String,
+    sink: CellSink<A>,
+    recorder: Recorder,
+    encode: Arc<dyn Fn(&A)->String+Send+Sync>
+}
+
+impl<A> Clone for RecordedCellSink<A> {
+    fn clone(&self) -> Self {
+        RecordedCellSink {
+            name: self.name.clone(),
+            sink: self.sink.clone(),
+            recorder: self.recorder.clone(),
+            encode: self.encode.clone()
+        }
+    }
+}
+
+impl<A:Clone+Send+'static> RecordedCellSink<A> {
+    pub fn cell(&self) -> Cell<A> {
+        self.sink.cell()
+    }
+
+    pub fn send(&self, a: A) {
+        self.recorder.sodium_ctx.transaction(|| {
+            self.recorder.record(&self.name, (self.encode)(&a));
+            self.sink.send(a);
+        });
+    }
+}
+
+// decodes a recorded value into the send that replays it
+type ReplaySink = Box<dyn Fn(&str)->Result<Box<dyn FnOnce()>,SodiumError>>;
+
+// Re-drives a freshly built graph from a trace written by a Recorder. Register a sink under each
+// name found in the trace, then replay sends the recorded values transaction by transaction.
+pub struct Replayer {
+    sodium_ctx: SodiumCtx,
+    transactions: Vec<Vec<(String,String)>>,
+    sinks: HashMap<String,ReplaySink>
+}
+
+impl Replayer {
+    pub fn parse<'a,LINES:IntoIterator<Item=&'a str>>(sodium_ctx: &SodiumCtx, lines: LINES) -> Result<Replayer,SodiumError> {
+        let mut events: Vec<(u64,u64,String,String)> = Vec::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 4 {
+                return Err(SodiumError::Replay(format!("line {}: expected 4 fields, found {}", i + 1, fields.len())));
+            }
+            let parse_number = |field: &str| field.parse::<u64>().map_err(|_| SodiumError::Replay(format!("line {}: bad number {}", i + 1, field)));
+            events.push((parse_number(fields[0])?, parse_number(fields[1])?, unescape(fields[2]), unescape(fields[3])));
+        }
+        events.sort_by_key(|(transaction_id, sequence, _, _)| (*transaction_id, *sequence));
+        let mut transactions: Vec<Vec<(String,String)>> = Vec::new();
+        let mut last_transaction_id_op: Option<u64> = None;
+        for (transaction_id, _, name, value) in events {
+            if last_transaction_id_op != Some(transaction_id) {
+                transactions.push(Vec::new());
+                last_transaction_id_op = Some(transaction_id);
+            }
+            transactions.last_mut().unwrap().push((name, value));
+        }
+        Ok(Replayer {
+            sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+            transactions,
+            sinks: HashMap::new()
+        })
+    }
+
+    pub fn stream_sink<A:Clone+Send+'static,DECODE:Fn(&str)->Result<A,SodiumError>+'static>(&mut self, name: &str, sink: &StreamSink<A>, decode: DECODE) {
+        let sink = sink.clone();
+        self.sinks.insert(name.to_string(), Box::new(move |value: &str| {
+            let a = decode(value)?;
+            let sink = sink.clone();
+            Ok(Box::new(move || sink.send(a)) as Box<dyn FnOnce()>)
+        }));
+    }
+
+    pub fn cell_sink<A:Clone+Send+'static,DECODE:Fn(&str)->Result<A,SodiumError>+'static>(&mut self, name: &str, sink: &CellSink<A>, decode: DECODE) {
+        let sink = sink.clone();
+        self.sinks.insert(name.to_string(), Box::new(move |value: &str| {
+            let a = decode(value)?;
+            let sink = sink.clone();
+            Ok(Box::new(move || sink.send(a)) as Box<dyn FnOnce()>)
+        }));
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    // Stops at the first value that names an unregistered sink or fails to decode. Every value of
+    // a transaction is decoded before it is opened, so a failing transaction sends nothing.
+    pub fn replay(&self) -> Result<(),SodiumError> {
+        for transaction in &self.transactions {
+            let mut sends = Vec::with_capacity(transaction.len());
+            for (name, value) in transaction {
+                match self.sinks.get(name) {
+                    Some(decode) => sends.push(decode(value)?),
+                    None => return Err(SodiumError::Replay(format!("no sink registered for {}", name)))
+                }
+            }
+            self.sodium_ctx.transaction(|| {
+                for send in sends {
+                    send();
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c)
+        }
+    }
+    result
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\')
+        }
+    }
+    result
+}
@@ -4,7 +4,20 @@ use crate::CellLoop;
 use crate::Stream;
 use crate::StreamSink;
 use crate::StreamLoop;
+use crate::Checkpoint;
+use crate::Clock;
+use crate::NodeProfile;
+use crate::SodiumError;
+use crate::PoisonPolicy;
+use crate::TransactionGuard;
+use crate::TransactionStats;
 use crate::impl_::sodium_ctx::SodiumCtx as SodiumCtxImpl;
+use crate::impl_::transaction_hooks::TransactionHooks;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct SodiumCtx {
     pub impl_: SodiumCtxImpl
@@ -43,7 +56,120 @@ impl SodiumCtx {
         StreamSink::new_with_coalescer(self, coalescer)
     }
 
+    pub fn new_cell_sink_with_coalescer<A:Clone+Send+'static,COALESCER:FnMut(&A,&A)->A+Send+'static>(&self, a: A, coalescer: COALESCER) -> CellSink<A> {
+        CellSink::new_with_coalescer(self, a, coalescer)
+    }
+
+    // In debug builds sampling a cell or listening to a stream inside a lambda that did not declare it
+    // as a dependency logs a warning. This turns that warning into a panic.
+    pub fn set_panic_on_untracked_deps(&self, panic_on_untracked_deps: bool) {
+        self.impl_.set_panic_on_untracked_deps(panic_on_untracked_deps);
+    }
+
+    // Decides whether a panic inside a listener or lambda leaves the streams and cells it touched
+    // unusable (Propagate, the default), or whether they and the transaction state are recovered
+    // so the context can keep being used (Recover). Under Recover the transaction that panicked is
+    // discarded, so cells keep the values they had before it.
+    pub fn set_poison_policy(&self, poison_policy: PoisonPolicy) {
+        self.impl_.set_poison_policy(poison_policy);
+    }
+
+    pub fn poison_policy(&self) -> PoisonPolicy {
+        self.impl_.poison_policy()
+    }
+
+    // the current values of all persistent cells, see Stream::hold_persistent, Stream::accum_persistent
+    // and CellSink::new_persistent
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.impl_.checkpoint()
+    }
+
+    // Seeds this context from a checkpoint. Call it before wiring, persistent cells created afterwards
+    // with a name found in the checkpoint start from the restored value instead of their default.
+    pub fn restore(&self, checkpoint: &Checkpoint) {
+        self.impl_.restore(checkpoint);
+    }
+
+    // Transactions on different threads run one at a time, a thread starting one waits for the
+    // transaction running on another thread to finish.
     pub fn transaction<R,K:FnOnce()->R>(&self, k: K) -> R {
         self.impl_.transaction(k)
     }
+
+    // Hooks are kept for the life of the context and run for every outermost transaction from then
+    // on, with the counters of that transaction. This one runs as the transaction opens.
+    pub fn on_transaction_start<HOOK:Fn(&TransactionStats)+Send+Sync+'static>(&self, hook: HOOK) {
+        self.impl_.add_hook(|hooks: &mut TransactionHooks| &mut hooks.start, Arc::new(hook));
+    }
+
+    // runs once every node has been updated, before the values fired in the transaction are cleared
+    pub fn on_propagation_done<HOOK:Fn(&TransactionStats)+Send+Sync+'static>(&self, hook: HOOK) {
+        self.impl_.add_hook(|hooks: &mut TransactionHooks| &mut hooks.propagation_done, Arc::new(hook));
+    }
+
+    // Runs once the cells have taken their new values and everything posted by the transaction has
+    // run. Also runs for a transaction discarded by TransactionGuard::abort, but not for one that a
+    // panic unwound, as hooks are not run while unwinding.
+    pub fn on_transaction_end<HOOK:Fn(&TransactionStats)+Send+Sync+'static>(&self, hook: HOOK) {
+        self.impl_.add_hook(|hooks: &mut TransactionHooks| &mut hooks.end, Arc::new(hook));
+    }
+
+    // runs after each cycle collection, with the counters of the transaction it followed
+    pub fn on_gc<HOOK:Fn(&TransactionStats)+Send+Sync+'static>(&self, hook: HOOK) {
+        self.impl_.add_hook(|hooks: &mut TransactionHooks| &mut hooks.gc, Arc::new(hook));
+    }
+
+    // While on, every node update is counted and timed, see profile_report. Off by default as it
+    // costs a clock read per update.
+    pub fn set_profiling(&self, profiling: bool) {
+        self.impl_.set_profiling(profiling);
+    }
+
+    // One entry per node that updated while profiling was on, sorted by node name, then by the
+    // label given by Stream::named or Cell::named. An entry is removed when its node is freed.
+    pub fn profile_report(&self) -> Vec<NodeProfile> {
+        self.impl_.profile_report()
+    }
+
+    pub fn clear_profile(&self) {
+        self.impl_.clear_profile();
+    }
+
+    // Where the futures of Stream::map_async and Cell::map_async are run, typically by handing them to
+    // the application's async runtime. It must be set before map_async is used, which panics otherwise.
+    pub fn set_async_spawner<SPAWNER:Fn(Pin<Box<dyn Future<Output=()>+Send>>)+Send+Sync+'static>(&self, spawner: SPAWNER) {
+        self.impl_.set_async_spawner(Arc::new(spawner));
+    }
+
+    // The clock the timer operators such as Stream::delay and Cell::sample_every go by. Defaults to
+    // a SystemClock, tests can use a ManualClock instead.
+    pub fn set_clock<CLOCK:Clock+'static>(&self, clock: CLOCK) {
+        self.impl_.set_clock(Arc::new(clock));
+    }
+
+    pub fn now(&self) -> Duration {
+        self.impl_.now()
+    }
+
+    // Runs the timers that are due by the clock, each sending in a transaction of its own. Nothing
+    // runs them for you, call this from the application's event loop, see time_to_next_timer.
+    pub fn run_timers(&self) {
+        self.impl_.run_timers();
+    }
+
+    // how long until the next timer is due, zero if one is overdue
+    pub fn time_to_next_timer(&self) -> Option<Duration> {
+        self.impl_.time_to_next_timer()
+    }
+
+    // For a transaction that spans several functions. Everything sent until the guard is committed
+    // or dropped happens in the one transaction, which must end on the thread that began it.
+    pub fn begin(&self) -> TransactionGuard {
+        self.impl_.begin()
+    }
+
+    // like transaction, but returns an error if any StreamLoop or CellLoop created inside it was not looped
+    pub fn try_transaction<R,K:FnOnce()->R>(&self, k: K) -> Result<R,SodiumError> {
+        self.impl_.try_transaction(k)
+    }
 }
@@ -1,13 +1,20 @@
 use crate::cell::Cell;
+use crate::cell_vec::SharedVec;
+use crate::either::Either;
 use crate::impl_::dep::Dep;
 use crate::impl_::stream::Stream as StreamImpl;
 use crate::impl_::lambda::IsLambda1;
 use crate::impl_::lambda::IsLambda2;
 use crate::impl_::lambda::IsLambda3;
-use crate::impl_::lambda::{lambda1, lambda2};
+use crate::impl_::lambda::{lambda1, lambda1_deps, lambda2, lambda2_deps};
+use crate::impl_::lambda::Traceable;
 use crate::Lazy;
 use crate::listener::Listener;
 use crate::sodium_ctx::SodiumCtx;
+use crate::SodiumError;
+
+use std::future::Future;
+use std::time::Duration;
 
 pub struct Stream<A> {
     pub impl_: StreamImpl<A>
@@ -21,6 +28,12 @@ impl<A> Clone for Stream<A> {
     }
 }
 
+impl<A> Traceable for Stream<A> {
+    fn deps(&self) -> Vec<Dep> {
+        vec![self.impl_.to_dep()]
+    }
+}
+
 impl<A:Clone+Send+'static> Stream<Option<A>> {
     pub fn filter_option(&self) -> Stream<A> {
         self.filter(|a: &Option<A>| a.is_some()).map(|a: &Option<A>| a.clone().unwrap())
@@ -48,12 +61,13 @@ impl<A:Clone+Send+'static> Stream<A> {
     }
 
     pub fn snapshot3<B:Send+Clone+'static,C:Send+Clone+'static,D:Send+Clone+'static,FN:IsLambda3<A,B,C,D>+Send+Sync+'static>(&self, cb: &Cell<B>, cc: &Cell<C>, mut f: FN) -> Stream<D> {
-        let deps: Vec<Dep>;
+        let mut deps: Vec<Dep>;
         if let Some(deps2) = f.deps_op() {
             deps = deps2.clone();
         } else {
             deps = Vec::new();
         }
+        deps.push(cc.to_dep());
         let cc = cc.clone();
         self.snapshot(cb, lambda2(move |a: &A, b: &B| f.call(a, b, &cc.sample()), deps))
     }
@@ -78,6 +92,27 @@ impl<A:Clone+Send+'static> Stream<A> {
         Stream { impl_: self.impl_.merge(&s2.impl_, f) }
     }
 
+    // Fires once per transaction with whichever of the two streams fired in it.
+    pub fn join_simultaneous<B:Clone+Send+'static>(&self, s2: &Stream<B>) -> Stream<(Option<A>,Option<B>)> {
+        self.map(|a: &A| (Some(a.clone()), None))
+            .merge(
+                &s2.map(|b: &B| (None, Some(b.clone()))),
+                |(a, _): &(Option<A>,Option<B>), (_, b): &(Option<A>,Option<B>)| (a.clone(), b.clone())
+            )
+    }
+
+    // Like or_else for streams of different types, when both fire in the same transaction only
+    // this one's event is kept. Use join_simultaneous to see both.
+    pub fn merge_either<B:Clone+Send+'static>(&self, s2: &Stream<B>) -> Stream<Either<A,B>> {
+        self.join_simultaneous(s2).map(|ab: &(Option<A>,Option<B>)| {
+            match ab {
+                (Some(a), _) => Either::Left(a.clone()),
+                (None, Some(b)) => Either::Right(b.clone()),
+                (None, None) => unreachable!()
+            }
+        })
+    }
+
     pub fn hold(&self, a: A) -> Cell<A> {
         Cell { impl_: self.impl_.hold(a) }
     }
@@ -92,6 +127,55 @@ impl<A:Clone+Send+'static> Stream<A> {
         self.filter(lambda1(move |_: &A| cpred.sample(), vec![cpred_dep]))
     }
 
+    // The timer operators fire from timers run by SodiumCtx::run_timers, each in a transaction of its
+    // own like Operational::defer.
+    pub fn delay(&self, period: Duration) -> Stream<A> {
+        Stream { impl_: self.impl_.delay(period) }
+    }
+
+    // The result of the future f makes for each event, fired in a transaction of its own once it is
+    // done. The futures run on the spawner given to SodiumCtx::set_async_spawner. Only the latest
+    // event's result is fired, the results of futures overtaken by a later event are dropped.
+    // Panics if no spawner has been set.
+    pub fn map_async<B,FUT,FN>(&self, f: FN) -> Stream<B>
+        where B: Send + Clone + 'static,
+              FUT: Future<Output=B> + Send + 'static,
+              FN: IsLambda1<A,FUT> + Send + Sync + 'static
+    {
+        Stream { impl_: self.impl_.map_async(f) }
+    }
+
+    // the latest event once none has followed it for the period
+    pub fn debounce(&self, period: Duration) -> Stream<A> {
+        Stream { impl_: self.impl_.debounce(period) }
+    }
+
+    // the first event straight away, then at most one per period, the latest of those held back
+    pub fn throttle(&self, period: Duration) -> Stream<A> {
+        Stream { impl_: self.impl_.throttle(period) }
+    }
+
+    // Fires what the stream f made for the latest event fires. Streams made for earlier events stop
+    // being listened to, from the transaction after the one that replaced them.
+    pub fn switch_map<B,FN>(&self, mut f: FN) -> Stream<B>
+        where B: Send + Clone + 'static,
+              FN: IsLambda1<A,Stream<B>> + Send + Sync + 'static
+    {
+        let f_deps = lambda1_deps(&f);
+        Stream { impl_: self.impl_.switch_map(lambda1(move |a: &A| f.call(a).impl_, f_deps)) }
+    }
+
+    // Fires what any of the streams f has made so far fires, they all stay listened to for as long
+    // as the result is. Values fired at once are combined by merge, oldest stream first.
+    pub fn merge_map<B,FN,MERGE>(&self, mut f: FN, merge: MERGE) -> Stream<B>
+        where B: Send + Clone + 'static,
+              FN: IsLambda1<A,Stream<B>> + Send + Sync + 'static,
+              MERGE: IsLambda2<B,B,B> + Send + Sync + 'static
+    {
+        let f_deps = lambda1_deps(&f);
+        Stream { impl_: self.impl_.merge_map(lambda1(move |a: &A| f.call(a).impl_, f_deps), merge) }
+    }
+
     pub fn once(&self) -> Stream<A> {
         Stream { impl_: self.impl_.once() }
     }
@@ -126,11 +210,109 @@ impl<A:Clone+Send+'static> Stream<A> {
         Cell { impl_: self.impl_.accum_lazy(init_state, f) }
     }
 
+    // Every n events as one Vec. The events of a window that has not filled yet are held back.
+    pub fn window_count(&self, n: usize) -> Stream<Vec<A>> where A: Sync {
+        assert!(n > 0, "window_count needs windows of at least one event");
+        // a SharedVec state so that an event doesn't copy the window, only the path it pushes onto
+        self.collect_lazy(Lazy::new(SharedVec::new), move |a: &A, window: &SharedVec<A>| {
+            let mut window = window.clone();
+            window.push(a.clone());
+            if window.len() == n {
+                (Some(window.to_vec()), SharedVec::new())
+            } else {
+                (None, window)
+            }
+        }).filter_option()
+    }
+
+    // The events of each period as one Vec, fired as the period ends. Periods without events are
+    // skipped. The periods are timed by SodiumCtx::run_timers.
+    pub fn window_time(&self, period: Duration) -> Stream<Vec<A>> where A: Sync {
+        let ticks = Stream { impl_: StreamImpl::periodic(&self.impl_.sodium_ctx(), period) };
+        self.map(|a: &A| Some(a.clone()))
+            .or_else(&ticks.map(|_: &()| None))
+            .collect_lazy(Lazy::new(SharedVec::new), |a_op: &Option<A>, window: &SharedVec<A>| {
+                match a_op {
+                    Some(a) => {
+                        let mut window = window.clone();
+                        window.push(a.clone());
+                        (None, window)
+                    },
+                    None if window.is_empty() => (None, SharedVec::new()),
+                    None => (Some(window.to_vec()), SharedVec::new())
+                }
+            })
+            .filter_option()
+    }
+
+    // Folds the last n events into a cell, such as a moving sum. add folds in each new event, and
+    // remove takes out the event that drops out of the window, so the window is never refolded.
+    pub fn sliding_fold<S,ADD,REMOVE>(&self, n: usize, init_state: S, mut add: ADD, mut remove: REMOVE) -> Cell<S>
+        where A: Sync,
+              S: Send + Clone + 'static,
+              ADD: IsLambda2<A,S,S> + Send + Sync + 'static,
+              REMOVE: IsLambda2<A,S,S> + Send + Sync + 'static
+    {
+        assert!(n > 0, "sliding_fold needs windows of at least one event");
+        let mut deps = lambda2_deps(&add);
+        deps.append(&mut lambda2_deps(&remove));
+        self.accum_lazy(
+            Lazy::new(move || (SharedVec::new(), init_state.clone())),
+            lambda2(move |a: &A, (window, s): &(SharedVec<A>,S)| {
+                let mut window = window.clone();
+                let mut s = add.call(a, s);
+                window.push(a.clone());
+                if window.len() > n {
+                    let old = window.remove(0);
+                    s = remove.call(&old, &s);
+                }
+                (window, s)
+            }, deps)
+        ).map(|(_, s): &(SharedVec<A>,S)| s.clone())
+    }
+
+    // a hold whose value is saved by SodiumCtx::checkpoint and seeded by SodiumCtx::restore under name
+    pub fn hold_persistent<ENCODE,DECODE>(&self, name: &str, a: A, encode: ENCODE, decode: DECODE) -> Result<Cell<A>,SodiumError>
+        where ENCODE: Fn(&A)->Vec<u8> + Send + 'static,
+              DECODE: FnOnce(&[u8])->Result<A,SodiumError>
+    {
+        let a = self.impl_.sodium_ctx().restored_or(name, a, decode)?;
+        let c = self.hold(a);
+        c.impl_.persist(name, encode);
+        Ok(c)
+    }
+
+    // an accum whose state is saved by SodiumCtx::checkpoint and seeded by SodiumCtx::restore under name
+    pub fn accum_persistent<S,F,ENCODE,DECODE>(&self, name: &str, init_state: S, f: F, encode: ENCODE, decode: DECODE) -> Result<Cell<S>,SodiumError>
+        where S: Send + Clone + 'static,
+              F: IsLambda2<A,S,S> + Send + Sync + 'static,
+              ENCODE: Fn(&S)->Vec<u8> + Send + 'static,
+              DECODE: FnOnce(&[u8])->Result<S,SodiumError>
+    {
+        let init_state = self.impl_.sodium_ctx().restored_or(name, init_state, decode)?;
+        let c = self.accum(init_state, f);
+        c.impl_.persist(name, encode);
+        Ok(c)
+    }
+
+    // run cleanup once this stream is freed, e.g. to close a resource feeding it
+    pub fn add_cleanup<CLEANUP:FnOnce()+Send+Sync+'static>(&self, cleanup: CLEANUP) -> Stream<A> {
+        Stream { impl_: self.impl_.add_cleanup(cleanup) }
+    }
+
+    // labels this stream's entry in SodiumCtx::profile_report, to tell it apart from other streams
+    // made by the same operator
+    pub fn named(&self, label: &str) -> Stream<A> {
+        Stream { impl_: self.impl_.named(label) }
+    }
+
     pub fn listen_weak<K:IsLambda1<A,()>+Send+Sync+'static>(&self, k: K) -> Listener {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "listened to stream");
         Listener { impl_: self.impl_.listen_weak(k) }
     }
 
     pub fn listen<K:IsLambda1<A,()>+Send+Sync+'static>(&self, k: K) -> Listener {
+        self.impl_.node().sodium_ctx.check_tracked(&self.impl_.node().gc_node, "listened to stream");
         Listener { impl_: self.impl_.listen(k) }
     }
 }
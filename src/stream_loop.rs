@@ -1,5 +1,6 @@
 use crate::impl_::stream_loop::StreamLoop as StreamLoopImpl;
 use crate::SodiumCtx;
+use crate::SodiumError;
 use crate::Stream;
 
 pub struct StreamLoop<A> {
@@ -19,4 +20,8 @@ impl<A:Send+Clone+'static> StreamLoop<A> {
     pub fn loop_(&self, sa: &Stream<A>) {
         self.impl_.loop_(&sa.impl_);
     }
+
+    pub fn try_loop(&self, sa: &Stream<A>) -> Result<(),SodiumError> {
+        self.impl_.try_loop(&sa.impl_)
+    }
 }
@@ -1,6 +1,9 @@
 use crate::impl_::stream_sink::StreamSink as StreamSinkImpl;
 use crate::sodium_ctx::SodiumCtx;
 use crate::stream::Stream;
+use crate::Dep;
+use crate::SodiumError;
+use crate::Traceable;
 
 pub struct StreamSink<A> {
     pub impl_: StreamSinkImpl<A>
@@ -14,6 +17,12 @@ impl<A> Clone for StreamSink<A> {
     }
 }
 
+impl<A:Send+'static> Traceable for StreamSink<A> {
+    fn deps(&self) -> Vec<Dep> {
+        vec![self.impl_.stream().to_dep()]
+    }
+}
+
 impl<A:Clone+Send+'static> StreamSink<A> {
     pub fn new(sodium_ctx: &SodiumCtx) -> StreamSink<A> {
         StreamSink { impl_: StreamSinkImpl::new(&sodium_ctx.impl_) }
@@ -30,4 +39,19 @@ impl<A:Clone+Send+'static> StreamSink<A> {
     pub fn send(&self, a: A) {
         self.impl_.send(a);
     }
+
+    pub fn try_send(&self, a: A) -> Result<(),SodiumError> {
+        self.impl_.try_send(a)
+    }
+
+    // Each value is sent in a transaction of its own, with one cycle collection for the whole batch
+    // instead of one per value. Transactions on other threads wait until the batch is done. Returns
+    // SodiumError::SendAllInTransaction inside a transaction.
+    pub fn send_all<I:IntoIterator<Item=A>>(&self, as_: I) {
+        self.impl_.send_all(as_);
+    }
+
+    pub fn try_send_all<I:IntoIterator<Item=A>>(&self, as_: I) -> Result<(),SodiumError> {
+        self.impl_.try_send_all(as_)
+    }
 }
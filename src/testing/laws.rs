@@ -0,0 +1,406 @@
+use crate::Cell;
+use crate::CellSink;
+use crate::Listener;
+use crate::Operational;
+use crate::SodiumCtx;
+use crate::Stream;
+use crate::StreamSink;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// how many operators deep LawGraph::stream and LawGraph::cell go
+const MAX_DEPTH: usize = 3;
+
+// Checks FRP laws against random graphs driven by random event schedules. Each case builds a
+// fresh SodiumCtx with a few stream and cell sinks, lets the law build two outputs from them,
+// then sends a random schedule of events and checks both outputs saw the same thing on every
+// tick. Each tick is one transaction and often carries several events, since simultaneous events
+// are where regressions tend to hide. After each case all nodes must have been freed.
+//
+// Case i uses the seed seed + i, and a failing case panics with its seed, so it can be run on its
+// own with LawCheck::new(seed).cases(1).
+//
+//     LawCheck::new(0).cases(200).check_all();
+//     LawCheck::new(0).check("filter fusion", |graph: &mut LawGraph| {
+//         let s = graph.stream();
+//         (s.filter(|a: &i32| *a > 2).filter(|a: &i32| *a < 7), s.filter(|a: &i32| *a > 2 && *a < 7))
+//     });
+pub struct LawCheck {
+    seed: u64,
+    cases: usize,
+    ticks: usize,
+    stream_inputs: usize,
+    cell_inputs: usize
+}
+
+impl LawCheck {
+    pub fn new(seed: u64) -> LawCheck {
+        LawCheck {
+            seed,
+            cases: 100,
+            ticks: 8,
+            stream_inputs: 3,
+            cell_inputs: 2
+        }
+    }
+
+    pub fn cases(mut self, cases: usize) -> LawCheck {
+        self.cases = cases;
+        self
+    }
+
+    pub fn ticks(mut self, ticks: usize) -> LawCheck {
+        self.ticks = ticks;
+        self
+    }
+
+    // the number of stream sinks and cell sinks each case has, both must be at least 1
+    pub fn inputs(mut self, stream_inputs: usize, cell_inputs: usize) -> LawCheck {
+        assert!(stream_inputs > 0 && cell_inputs > 0, "a law check needs at least one stream sink and one cell sink");
+        self.stream_inputs = stream_inputs;
+        self.cell_inputs = cell_inputs;
+        self
+    }
+
+    // Checks the two streams the law builds fire the same values on the same ticks.
+    pub fn check<A,LAW>(&self, name: &str, law: LAW)
+        where A: Clone + Debug + PartialEq + Send + 'static,
+              LAW: Fn(&mut LawGraph)->(Stream<A>,Stream<A>)
+    {
+        self.run(name, |graph: &mut LawGraph| {
+            let (left, right) = law(graph);
+            (Probe::stream(&left), Probe::stream(&right))
+        });
+    }
+
+    // Checks the two cells the law builds have the same value at the start and after every tick.
+    pub fn check_cells<A,LAW>(&self, name: &str, law: LAW)
+        where A: Clone + Debug + PartialEq + Send + 'static,
+              LAW: Fn(&mut LawGraph)->(Cell<A>,Cell<A>)
+    {
+        self.run(name, |graph: &mut LawGraph| {
+            let (left, right) = law(graph);
+            (Probe::cell(&left), Probe::cell(&right))
+        });
+    }
+
+    pub fn check_all(&self) {
+        self.check("map fusion", |graph: &mut LawGraph| {
+            let s = graph.stream();
+            let k1 = graph.value();
+            let k2 = graph.value();
+            let f = move |a: &i32| a.wrapping_add(k1);
+            let g = move |a: &i32| a.wrapping_mul(k2);
+            (s.map(f).map(g), s.map(move |a: &i32| g(&f(a))))
+        });
+        self.check("merge associativity", |graph: &mut LawGraph| {
+            // concatenation is associative but not commutative, so it also catches operands being swapped
+            let concat = |l: &Vec<i32>, r: &Vec<i32>| {
+                let mut result = l.clone();
+                result.extend(r.iter().cloned());
+                result
+            };
+            let s1 = graph.stream().map(|a: &i32| vec![*a]);
+            let s2 = graph.stream().map(|a: &i32| vec![*a]);
+            let s3 = graph.stream().map(|a: &i32| vec![*a]);
+            (s1.merge(&s2, concat).merge(&s3, concat), s1.merge(&s2.merge(&s3, concat), concat))
+        });
+        self.check("hold then updates", |graph: &mut LawGraph| {
+            let s = graph.stream();
+            let init = graph.value();
+            (s.hold(init).updates(), s)
+        });
+        self.check_cells("updates then hold", |graph: &mut LawGraph| {
+            let c = graph.cell();
+            let c2 = graph.sodium_ctx().transaction(|| c.updates().hold(c.sample()));
+            (c2, c)
+        });
+        self.check_cells("switch_c of a constant cell", |graph: &mut LawGraph| {
+            let c = graph.cell();
+            (Cell::switch_c(&Cell::new(graph.sodium_ctx(), c.clone())), c)
+        });
+        self.check("updates of switch_c of a constant cell", |graph: &mut LawGraph| {
+            let c = graph.cell();
+            (Cell::switch_c(&Cell::new(graph.sodium_ctx(), c.clone())).updates(), c.updates())
+        });
+        self.check("defer ordering", |graph: &mut LawGraph| {
+            let s = graph.stream();
+            (Operational::defer(&s), s)
+        });
+    }
+
+    fn run<A,BUILD>(&self, name: &str, build: BUILD)
+        where A: Clone + Debug + PartialEq + Send + 'static,
+              BUILD: Fn(&mut LawGraph)->(Probe<A>,Probe<A>)
+    {
+        for case in 0..self.cases {
+            self.run_case(name, self.seed.wrapping_add(case as u64), &build);
+        }
+    }
+
+    fn run_case<A,BUILD>(&self, name: &str, seed: u64, build: &BUILD)
+        where A: Clone + Debug + PartialEq + Send + 'static,
+              BUILD: Fn(&mut LawGraph)->(Probe<A>,Probe<A>)
+    {
+        let sodium_ctx = SodiumCtx::new();
+        {
+            let mut rng = Rng::new(seed);
+            let stream_sinks: Vec<StreamSink<i32>> =
+                (0..self.stream_inputs)
+                    .map(|_| StreamSink::new_with_coalescer(&sodium_ctx, |l: &i32, r: &i32| l.wrapping_mul(10).wrapping_add(*r)))
+                    .collect();
+            let cell_sinks: Vec<CellSink<i32>> = (0..self.cell_inputs).map(|_| CellSink::new(&sodium_ctx, rng.value())).collect();
+            let mut graph = LawGraph {
+                sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+                stream_sinks,
+                cell_sinks,
+                rng
+            };
+            let (left, right) = build(&mut graph);
+            let schedule = graph.schedule(self.ticks);
+            left.sample(0);
+            right.sample(0);
+            for (tick, events) in schedule.iter().enumerate() {
+                left.set_tick(tick + 1);
+                right.set_tick(tick + 1);
+                sodium_ctx.transaction(|| {
+                    for event in events {
+                        match *event {
+                            Event::Stream(index, a) => graph.stream_sinks[index].send(a),
+                            Event::Cell(index, a) => graph.cell_sinks[index].send(a)
+                        }
+                    }
+                });
+                left.sample(tick + 1);
+                right.sample(tick + 1);
+            }
+            let left = left.finish();
+            let right = right.finish();
+            if left != right {
+                panic!(
+                    "law \"{}\" does not hold for seed {}\nschedule: {:?}\nleft:  {:?}\nright: {:?}",
+                    name, seed, schedule, left, right
+                );
+            }
+        }
+        sodium_ctx.impl_.collect_cycles();
+        let node_count = sodium_ctx.impl_.node_count();
+        if node_count != 0 {
+            panic!("law \"{}\" left {} nodes behind for seed {}", name, node_count, seed);
+        }
+    }
+}
+
+// The inputs of one case. Laws build their outputs from the sinks, or from random graphs over
+// them made with stream and cell.
+pub struct LawGraph {
+    sodium_ctx: SodiumCtx,
+    stream_sinks: Vec<StreamSink<i32>>,
+    cell_sinks: Vec<CellSink<i32>>,
+    rng: Rng
+}
+
+impl LawGraph {
+    pub fn sodium_ctx(&self) -> &SodiumCtx {
+        &self.sodium_ctx
+    }
+
+    // a random value of the kind the sinks are sent
+    pub fn value(&mut self) -> i32 {
+        self.rng.value()
+    }
+
+    pub fn input_stream(&mut self) -> Stream<i32> {
+        let index = self.rng.below(self.stream_sinks.len());
+        self.stream_sinks[index].stream()
+    }
+
+    pub fn input_cell(&mut self) -> Cell<i32> {
+        let index = self.rng.below(self.cell_sinks.len());
+        self.cell_sinks[index].cell()
+    }
+
+    // a random graph of operators over the sinks
+    pub fn stream(&mut self) -> Stream<i32> {
+        self.random_stream(MAX_DEPTH)
+    }
+
+    // a random graph of operators over the sinks
+    pub fn cell(&mut self) -> Cell<i32> {
+        self.random_cell(MAX_DEPTH)
+    }
+
+    fn random_stream(&mut self, depth: usize) -> Stream<i32> {
+        if depth == 0 {
+            return self.input_stream();
+        }
+        match self.rng.below(7) {
+            0 => self.input_stream(),
+            1 => {
+                let k = self.value();
+                self.random_stream(depth - 1).map(move |a: &i32| a.wrapping_add(k))
+            },
+            2 => self.random_stream(depth - 1).filter(|a: &i32| a % 2 == 0),
+            3 => {
+                let s1 = self.random_stream(depth - 1);
+                let s2 = self.random_stream(depth - 1);
+                s1.merge(&s2, |l: &i32, r: &i32| l.wrapping_mul(10).wrapping_add(*r))
+            },
+            4 => {
+                let s1 = self.random_stream(depth - 1);
+                let s2 = self.random_stream(depth - 1);
+                s1.or_else(&s2)
+            },
+            5 => {
+                let s = self.random_stream(depth - 1);
+                let c = self.random_cell(depth - 1);
+                s.snapshot(&c, |a: &i32, b: &i32| a.wrapping_sub(*b))
+            },
+            _ => self.random_cell(depth - 1).updates()
+        }
+    }
+
+    fn random_cell(&mut self, depth: usize) -> Cell<i32> {
+        if depth == 0 {
+            return self.input_cell();
+        }
+        match self.rng.below(5) {
+            0 => self.input_cell(),
+            1 => {
+                let init = self.value();
+                self.random_stream(depth - 1).hold(init)
+            },
+            2 => {
+                let k = self.value();
+                self.random_cell(depth - 1).map(move |a: &i32| a.wrapping_mul(k))
+            },
+            3 => {
+                let c1 = self.random_cell(depth - 1);
+                let c2 = self.random_cell(depth - 1);
+                c1.lift2(&c2, |a: &i32, b: &i32| a.wrapping_add(*b))
+            },
+            _ => {
+                let init = self.value();
+                self.random_stream(depth - 1).accum(init, |a: &i32, s: &i32| s.wrapping_add(*a))
+            }
+        }
+    }
+
+    // Up to three events per tick. Stream sinks may be sent several times in one tick, which their
+    // coalescers combine, but each cell sink at most once.
+    fn schedule(&mut self, ticks: usize) -> Vec<Vec<Event>> {
+        let mut schedule = Vec::with_capacity(ticks);
+        for _ in 0..ticks {
+            let mut events = Vec::new();
+            let mut cells_sent: Vec<usize> = Vec::new();
+            for _ in 0..self.rng.below(4) {
+                let a = self.value();
+                if self.rng.below(3) == 0 {
+                    let index = self.rng.below(self.cell_sinks.len());
+                    if !cells_sent.contains(&index) {
+                        cells_sent.push(index);
+                        events.push(Event::Cell(index, a));
+                    }
+                } else {
+                    events.push(Event::Stream(self.rng.below(self.stream_sinks.len()), a));
+                }
+            }
+            schedule.push(events);
+        }
+        schedule
+    }
+}
+
+#[derive(Debug)]
+enum Event {
+    Stream(usize,i32),
+    Cell(usize,i32)
+}
+
+// What one side of a law produced, as (tick, value) pairs. Tick 0 is before any events are sent.
+struct Probe<A> {
+    tick: Arc<Mutex<usize>>,
+    values: Arc<Mutex<Vec<(usize,A)>>>,
+    listener_op: Option<Listener>,
+    cell_op: Option<Cell<A>>
+}
+
+impl<A:Clone+Send+'static> Probe<A> {
+    fn stream(sa: &Stream<A>) -> Probe<A> {
+        let tick = Arc::new(Mutex::new(0));
+        let values: Arc<Mutex<Vec<(usize,A)>>> = Arc::new(Mutex::new(Vec::new()));
+        let listener;
+        {
+            let tick = tick.clone();
+            let values = values.clone();
+            listener = sa.listen(move |a: &A| {
+                let tick = *tick.lock().unwrap();
+                values.lock().unwrap().push((tick, a.clone()));
+            });
+        }
+        Probe {
+            tick,
+            values,
+            listener_op: Some(listener),
+            cell_op: None
+        }
+    }
+
+    fn cell(ca: &Cell<A>) -> Probe<A> {
+        Probe {
+            tick: Arc::new(Mutex::new(0)),
+            values: Arc::new(Mutex::new(Vec::new())),
+            listener_op: None,
+            cell_op: Some(ca.clone())
+        }
+    }
+
+    fn set_tick(&self, tick: usize) {
+        *self.tick.lock().unwrap() = tick;
+    }
+
+    // called once each tick's transaction is over
+    fn sample(&self, tick: usize) {
+        if let Some(ref ca) = self.cell_op {
+            self.values.lock().unwrap().push((tick, ca.sample()));
+        }
+    }
+
+    fn finish(self) -> Vec<(usize,A)> {
+        if let Some(ref listener) = self.listener_op {
+            listener.unlisten();
+        }
+        let values = self.values.lock().unwrap().clone();
+        values
+    }
+}
+
+// splitmix64, good enough to pick graphs and schedules and reproducible from the seed alone
+struct Rng {
+    state: u64
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % (n as u64)) as usize
+    }
+
+    // small values, so filters and equal values come up often
+    fn value(&mut self) -> i32 {
+        self.below(10) as i32
+    }
+}
@@ -0,0 +1,181 @@
+use crate::Cell;
+use crate::Listener;
+use crate::SodiumCtx;
+use crate::Stream;
+use crate::StreamSink;
+
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// Drives FRP logic from marble diagrams. Each position in a marble string is one tick, and each
+// tick runs as one transaction:
+//
+//     -      nothing happens
+//     a      the event a
+//     (cd)   the events c and d, sent in the same transaction
+//
+// Spaces are ignored, so diagrams can be lined up. Inputs are streams of chars. Expected outputs
+// use the same notation: an output that did not fire is -, a value that does not render as a
+// single char is wrapped in parentheses, e.g. (12).
+//
+//     let sodium_ctx = SodiumCtx::new();
+//     let mut marble_test = MarbleTest::new(&sodium_ctx);
+//     let s1 = marble_test.stream_input("a-b-c");
+//     let s2 = marble_test.stream_input("x---y");
+//     marble_test.expect(&s1.or_else(&s2), "a-b-c");
+//     marble_test.expect(&s2.or_else(&s1), "x-b-y");
+//     marble_test.run();
+pub struct MarbleTest {
+    sodium_ctx: SodiumCtx,
+    inputs: Vec<(Vec<Vec<char>>,StreamSink<char>)>,
+    outputs: Vec<MarbleOutput>,
+    tick: Arc<Mutex<usize>>
+}
+
+struct MarbleOutput {
+    description: String,
+    expected: Vec<Vec<char>>,
+    ticks: Arc<Mutex<Vec<Vec<String>>>>,
+    source: MarbleSource
+}
+
+enum MarbleSource {
+    Stream(Listener),
+    // sampled once each tick's transaction is over
+    Cell(Box<dyn Fn()->String>)
+}
+
+impl MarbleTest {
+    pub fn new(sodium_ctx: &SodiumCtx) -> MarbleTest {
+        MarbleTest {
+            sodium_ctx: SodiumCtx { impl_: sodium_ctx.impl_.clone() },
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            tick: Arc::new(Mutex::new(0))
+        }
+    }
+
+    pub fn stream_input(&mut self, marble: &str) -> Stream<char> {
+        self.add_input(marble, StreamSink::new(&self.sodium_ctx))
+    }
+
+    // for inputs with groups, decides what a stream sent several chars in one tick fires
+    pub fn stream_input_with_coalescer<COALESCER:FnMut(&char,&char)->char+Send+'static>(&mut self, marble: &str, coalescer: COALESCER) -> Stream<char> {
+        self.add_input(marble, StreamSink::new_with_coalescer(&self.sodium_ctx, coalescer))
+    }
+
+    fn add_input(&mut self, marble: &str, sink: StreamSink<char>) -> Stream<char> {
+        let stream = sink.stream();
+        self.inputs.push((parse_marble(marble), sink));
+        stream
+    }
+
+    // checks what the stream fires on each tick
+    pub fn expect<A:Display+Clone+Send+'static>(&mut self, sa: &Stream<A>, expected: &str) {
+        let ticks: Arc<Mutex<Vec<Vec<String>>>> = Arc::new(Mutex::new(Vec::new()));
+        let listener;
+        {
+            let ticks = ticks.clone();
+            let tick = self.tick.clone();
+            listener = sa.listen(move |a: &A| record(&ticks, *tick.lock().unwrap(), a.to_string()));
+        }
+        self.add_output(format!("stream #{}", self.outputs.len()), expected, ticks, MarbleSource::Stream(listener));
+    }
+
+    // checks the value the cell has after each tick
+    pub fn expect_cell<A:Display+Clone+Send+'static>(&mut self, ca: &Cell<A>, expected: &str) {
+        let ca = ca.clone();
+        self.add_output(format!("cell #{}", self.outputs.len()), expected, Arc::new(Mutex::new(Vec::new())), MarbleSource::Cell(Box::new(move || ca.sample().to_string())));
+    }
+
+    fn add_output(&mut self, description: String, expected: &str, ticks: Arc<Mutex<Vec<Vec<String>>>>, source: MarbleSource) {
+        self.outputs.push(MarbleOutput {
+            description,
+            expected: parse_marble(expected),
+            ticks,
+            source
+        });
+    }
+
+    // Runs every tick and panics describing the first output that differs from what was expected.
+    pub fn run(self) {
+        let num_ticks =
+            self.inputs.iter().map(|(ticks, _)| ticks.len())
+                .chain(self.outputs.iter().map(|output: &MarbleOutput| output.expected.len()))
+                .max()
+                .unwrap_or(0);
+        for tick in 0..num_ticks {
+            *self.tick.lock().unwrap() = tick;
+            self.sodium_ctx.transaction(|| {
+                for (ticks, sink) in &self.inputs {
+                    if let Some(events) = ticks.get(tick) {
+                        for event in events {
+                            sink.send(*event);
+                        }
+                    }
+                }
+            });
+            for output in &self.outputs {
+                if let MarbleSource::Cell(ref sample) = output.source {
+                    record(&output.ticks, tick, sample());
+                }
+            }
+        }
+        for output in &self.outputs {
+            if let MarbleSource::Stream(ref listener) = output.source {
+                listener.unlisten();
+            }
+            let l = output.ticks.lock();
+            let ticks: &Vec<Vec<String>> = l.as_ref().unwrap();
+            let actual: String = (0..num_ticks).map(|tick: usize| render_tick(ticks.get(tick))).collect();
+            let expected: String = (0..num_ticks).map(|tick: usize| {
+                render_tick(output.expected.get(tick).map(|events: &Vec<char>| events.iter().map(|c: &char| c.to_string()).collect()).as_ref())
+            }).collect();
+            assert_eq!(expected, actual, "{} did not match its marble diagram", output.description);
+        }
+    }
+}
+
+fn record(ticks: &Arc<Mutex<Vec<Vec<String>>>>, tick: usize, value: String) {
+    let mut l = ticks.lock();
+    let ticks: &mut Vec<Vec<String>> = l.as_mut().unwrap();
+    while ticks.len() <= tick {
+        ticks.push(Vec::new());
+    }
+    ticks[tick].push(value);
+}
+
+fn render_tick(events_op: Option<&Vec<String>>) -> String {
+    match events_op {
+        None => "-".to_string(),
+        Some(events) => {
+            if events.is_empty() {
+                "-".to_string()
+            } else if events.len() == 1 && events[0].chars().count() == 1 {
+                events[0].clone()
+            } else {
+                format!("({})", events.concat())
+            }
+        }
+    }
+}
+
+fn parse_marble(marble: &str) -> Vec<Vec<char>> {
+    let mut result = Vec::new();
+    let mut group_op: Option<Vec<char>> = None;
+    for c in marble.chars() {
+        match (c, &mut group_op) {
+            (' ', _) => (),
+            ('(', None) => group_op = Some(Vec::new()),
+            (')', Some(_)) => result.push(group_op.take().unwrap()),
+            (c, Some(group)) => group.push(c),
+            ('-', None) => result.push(Vec::new()),
+            (c, None) => result.push(vec![c])
+        }
+    }
+    if group_op.is_some() {
+        panic!("unclosed group in marble diagram {}", marble);
+    }
+    result
+}
@@ -0,0 +1,6 @@
+mod laws;
+mod marble;
+
+pub use self::laws::LawCheck;
+pub use self::laws::LawGraph;
+pub use self::marble::MarbleTest;
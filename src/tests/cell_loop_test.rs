@@ -1,5 +1,6 @@
 use crate::Operational;
 use crate::SodiumCtx;
+use crate::SodiumError;
 use crate::tests::assert_memory_freed;
 
 use std::sync::Arc;
@@ -107,3 +108,59 @@ fn lift_loop() {
     }
     assert_memory_freed(sodium_ctx);
 }
+
+#[test]
+fn loop_errors_without_panicking() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let result = sodium_ctx.try_transaction(
+            || {
+                let a = sodium_ctx.new_cell_loop::<i32>();
+                let r1 = a.cell().try_sample();
+                let r2 = a.try_loop(&sodium_ctx.new_cell(1));
+                let r3 = a.try_loop(&sodium_ctx.new_cell(2));
+                (r1, r2, r3, a.cell().try_sample())
+            }
+        );
+        assert_eq!(
+            Ok((Err(SodiumError::CellLoopSampledBeforeLooped), Ok(()), Err(SodiumError::AlreadyLooped), Ok(1))),
+            result
+        );
+        let result = sodium_ctx.try_transaction(
+            || {
+                let _a = sodium_ctx.new_cell_loop::<i32>();
+                let _b = sodium_ctx.new_stream_loop::<i32>();
+            }
+        );
+        assert_eq!(Err(SodiumError::NotLooped(2)), result);
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn lazy_loop_values() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let (a, b) = sodium_ctx.transaction(|| {
+            let a = sodium_ctx.new_cell_loop::<i32>();
+            // neither map_lazy nor loop_ need a's value, so it can be defined in terms of a cell built from it
+            let b = a.cell().map_lazy(|x: &i32| x * 10);
+            a.loop_(&s.stream().hold_lazy(sodium_ctx.new_cell(1).sample_lazy().map(|x: &i32| x + 1)));
+            (a.cell(), b)
+        });
+        assert_eq!(2, a.sample());
+        assert_eq!(20, b.sample());
+        s.send(3);
+        assert_eq!(30, b.sample());
+        let c = sodium_ctx.transaction(|| {
+            let c = sodium_ctx.new_cell_loop::<i32>();
+            c.loop_(&c.cell().map_lazy(|x: &i32| x + 1));
+            c.cell()
+        });
+        assert_eq!(Err(SodiumError::LazyCycle), c.try_sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
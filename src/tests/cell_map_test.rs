@@ -0,0 +1,104 @@
+use crate::CellMapSink;
+use crate::MapDiff;
+use crate::SodiumCtx;
+use crate::tests::assert_memory_freed;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[test]
+fn map_filter_fold_process_only_diffs() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sink: CellMapSink<&str,i32> = CellMapSink::new(sodium_ctx, vec![("a", 1), ("b", 2)].into_iter().collect());
+        let labels = sink.cell_map().map(|k: &&str, v: &i32| format!("{}={}", k, v));
+        let big = sink.cell_map().filter(|_: &&str, v: &i32| *v > 1);
+        let total = sink.cell_map().fold(0, |b: &i32, _: &&str, v: &i32| b + v, |b: &i32, _: &&str, v: &i32| b - v);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = big.diffs().listen(move |diffs: &Vec<MapDiff<&'static str,i32>>| out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        sink.insert("a", 5);
+        sink.remove(&"b");
+        sink.insert("c", 0);
+        l.unlisten();
+        let expected: BTreeMap<&str,String> = vec![("a", "a=5".to_string()), ("c", "c=0".to_string())].into_iter().collect();
+        assert_eq!(expected, labels.cell().sample().to_map());
+        assert_eq!(vec![("a", 5)].into_iter().collect::<BTreeMap<&str,i32>>(), big.cell().sample().to_map());
+        assert_eq!(5, total.sample());
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<MapDiff<&str,i32>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![MapDiff::Insert { key: "a", value: 5 }],
+                    vec![MapDiff::Remove { key: "b", value: 2 }]
+                ],
+                *out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn filter_turns_updates_into_inserts_and_removes() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sink: CellMapSink<&str,i32> = CellMapSink::new(sodium_ctx, vec![("a", 1), ("b", 2)].into_iter().collect());
+        let big = sink.cell_map().filter(|_: &&str, v: &i32| *v > 1);
+        let labels = sink.cell_map().map(|k: &&str, v: &i32| format!("{}={}", k, v));
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = big.diffs().listen(move |diffs: &Vec<MapDiff<&'static str,i32>>| out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        let label_out = Arc::new(Mutex::new(Vec::new()));
+        let l2;
+        {
+            let label_out = label_out.clone();
+            l2 = labels.diffs().listen(move |diffs: &Vec<MapDiff<&'static str,String>>| label_out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        sink.insert("b", 3);
+        sink.insert("b", 0);
+        sink.insert("a", 4);
+        sink.remove(&"a");
+        l2.unlisten();
+        l.unlisten();
+        assert!(big.cell().sample().is_empty());
+        assert_eq!(Some(&"b=0".to_string()), labels.cell().sample().get(&"b"));
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<MapDiff<&str,i32>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![MapDiff::Update { key: "b", old: 2, new: 3 }],
+                    vec![MapDiff::Remove { key: "b", value: 3 }],
+                    vec![MapDiff::Insert { key: "a", value: 4 }],
+                    vec![MapDiff::Remove { key: "a", value: 4 }]
+                ],
+                *out
+            );
+        }
+        {
+            let l = label_out.lock();
+            let label_out: &Vec<Vec<MapDiff<&str,String>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![MapDiff::Update { key: "b", old: "b=2".to_string(), new: "b=3".to_string() }],
+                    vec![MapDiff::Update { key: "b", old: "b=3".to_string(), new: "b=0".to_string() }],
+                    vec![MapDiff::Update { key: "a", old: "a=1".to_string(), new: "a=4".to_string() }],
+                    vec![MapDiff::Remove { key: "a", value: "a=4".to_string() }]
+                ],
+                *label_out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
@@ -1,10 +1,21 @@
 use crate::Cell;
+use crate::CellSink;
+use crate::Checkpoint;
+use crate::Lazy;
+use crate::ManualClock;
 use crate::SodiumCtx;
+use crate::SodiumError;
+use crate::sodium_lambda;
 use crate::tests::assert_memory_freed;
 use crate::tests::init;
+use crate::tests::queue_futures;
+use crate::tests::run_future;
 
+use std::convert::TryInto;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn constant_cell() {
@@ -229,3 +240,266 @@ fn lift_cells_in_switch_c() {
   };
 
 }*/
+
+#[test]
+fn sodium_lambda_deps() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sa = sodium_ctx.new_stream_sink();
+        let cb = sodium_ctx.new_cell_sink(10);
+        let sc;
+        {
+            let cb2 = cb.cell();
+            sc = sa.stream().map(sodium_lambda!(move |a: &i32| *a + cb2.sample(), [cb2]));
+        }
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = sc.listen(
+                move |a: &i32|
+                    out.lock().as_mut().unwrap().push(*a)
+            );
+        }
+        sa.send(1);
+        cb.send(20);
+        sa.send(2);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![11, 22], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn lift12() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let cs: Vec<_> = (0..12).map(|i| sodium_ctx.new_cell_sink(i)).collect();
+        let c =
+            cs[0].cell().lift12(
+                &cs[1].cell(), &cs[2].cell(), &cs[3].cell(), &cs[4].cell(), &cs[5].cell(),
+                &cs[6].cell(), &cs[7].cell(), &cs[8].cell(), &cs[9].cell(), &cs[10].cell(), &cs[11].cell(),
+                |a: &i32, b: &i32, c: &i32, d: &i32, e: &i32, f: &i32, g: &i32, h: &i32, i: &i32, j: &i32, k: &i32, l: &i32|
+                    [*a, *b, *c, *d, *e, *f, *g, *h, *i, *j, *k, *l].iter().sum::<i32>()
+            );
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = c.listen(
+                move |a: &i32|
+                    out.lock().as_mut().unwrap().push(*a)
+            );
+        }
+        cs[11].send(111);
+        sodium_ctx.transaction(|| {
+            cs[0].send(100);
+            cs[5].send(105);
+        });
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![66, 166, 366], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn try_send_and_poisoned_lazy() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink(1);
+        assert_eq!(Ok(()), c.try_send(2));
+        assert_eq!(Ok(2), c.cell().try_sample());
+        let lazy: Lazy<i32> = Lazy::new(|| panic!("thunk failed"));
+        {
+            let lazy = lazy.clone();
+            assert!(thread::spawn(move || lazy.run()).join().is_err());
+        }
+        assert_eq!(Err(SodiumError::Poisoned), lazy.try_run());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn checkpoint_and_restore() {
+    let encode = |a: &i32| a.to_le_bytes().to_vec();
+    let decode = |bytes: &[u8]| -> Result<i32,SodiumError> {
+        let bytes: [u8; 4] = bytes.try_into().map_err(|_| SodiumError::Checkpoint("bad i32".to_string()))?;
+        Ok(i32::from_le_bytes(bytes))
+    };
+    let bytes;
+    {
+        let sodium_ctx = SodiumCtx::new();
+        let cs = CellSink::new_persistent(&sodium_ctx, "latest", 0, encode, decode).unwrap();
+        let s = sodium_ctx.new_stream_sink();
+        let total = s.stream().accum_persistent("total", 0, |a: &i32, total: &i32| total + a, encode, decode).unwrap();
+        cs.send(7);
+        s.send(2);
+        s.send(3);
+        assert_eq!(5, total.sample());
+        bytes = sodium_ctx.checkpoint().to_bytes();
+    }
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        sodium_ctx.restore(&Checkpoint::from_bytes(&bytes).unwrap());
+        let cs = CellSink::new_persistent(sodium_ctx, "latest", 0, encode, decode).unwrap();
+        let s = sodium_ctx.new_stream_sink();
+        let total = s.stream().accum_persistent("total", 0, |a: &i32, total: &i32| total + a, encode, decode).unwrap();
+        let other = s.stream().hold_persistent("other", 1, encode, decode).unwrap();
+        assert_eq!(7, cs.cell().sample());
+        assert_eq!(1, other.sample());
+        s.send(4);
+        assert_eq!(9, total.sample());
+        assert_eq!(Err(SodiumError::Checkpoint("truncated".to_string())), Checkpoint::from_bytes(&bytes[..bytes.len() - 1]));
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn coalesce_and_modify() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let c = sodium_ctx.new_cell_sink_with_coalescer(0, |a: &i32, b: &i32| a + b);
+        let n = CellSink::new(sodium_ctx, 10);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = c.cell().lift2(&n.cell(), |a: &i32, b: &i32| (*a, *b)).listen(move |x: &(i32,i32)| out.lock().as_mut().unwrap().push(*x));
+        }
+        sodium_ctx.transaction(|| {
+            c.send(1);
+            c.send(2);
+        });
+        n.modify(|n: &i32| n + 1);
+        sodium_ctx.transaction(|| {
+            n.modify(|n: &i32| n + 1);
+            n.modify(|n: &i32| n * 2);
+            c.send(5);
+            c.modify(|c: &i32| c * 10);
+        });
+        sodium_ctx.transaction(|| {
+            n.send(1);
+            n.modify(|n: &i32| n + 1);
+        });
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<(i32,i32)> = l.as_ref().unwrap();
+            assert_eq!(vec![(0, 10), (3, 10), (3, 11), (50, 24), (50, 2)], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "send called more than once in a transaction")]
+fn send_after_modify_without_coalescer_panics() {
+    let sodium_ctx = SodiumCtx::new();
+    let n = CellSink::new(&sodium_ctx, 10);
+    sodium_ctx.transaction(|| {
+        n.modify(|n: &i32| n + 1);
+        n.send(1);
+    });
+}
+
+#[test]
+fn lazy_combinators() {
+    let runs = Arc::new(Mutex::new(0));
+    let a: Lazy<i32>;
+    {
+        let runs = runs.clone();
+        a = Lazy::new(move || {
+            *runs.lock().unwrap() += 1;
+            2
+        });
+    }
+    let b = a.map(|a: &i32| a * 10);
+    let c = a.lift2(&b, |a: &i32, b: &i32| a + b);
+    let d = c.flat_map(|c: &i32| Lazy::of_value(*c + 1));
+    assert_eq!(0, *runs.lock().unwrap());
+    assert_eq!(23, d.run());
+    assert_eq!(22, c.run());
+    assert_eq!(1, *runs.lock().unwrap());
+    let e_op: Arc<Mutex<Option<Lazy<i32>>>> = Arc::new(Mutex::new(None));
+    let e;
+    {
+        let e_op = e_op.clone();
+        e = Lazy::new_fallible(move || e_op.lock().unwrap().clone().unwrap().try_run());
+    }
+    *e_op.lock().unwrap() = Some(e.map(|e: &i32| e + 1));
+    assert_eq!(Err(SodiumError::LazyCycle), e.try_run());
+    *e_op.lock().unwrap() = None;
+}
+
+#[test]
+fn sample_every() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clock = ManualClock::new();
+        sodium_ctx.set_clock(clock.clone());
+        let c = sodium_ctx.new_cell_sink(1);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = c.cell().sample_every(Duration::from_secs(1)).listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        clock.advance(Duration::from_millis(2500));
+        sodium_ctx.run_timers();
+        c.send(2);
+        clock.advance(Duration::from_millis(500));
+        sodium_ctx.run_timers();
+        l.unlisten();
+        // the tick left behind goes once it finds nobody listening
+        clock.advance(Duration::from_secs(1));
+        sodium_ctx.run_timers();
+        assert_eq!(None, sodium_ctx.time_to_next_timer());
+        assert_eq!(vec![1, 1, 2], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn map_async() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let spawned = queue_futures(sodium_ctx);
+        let c = sodium_ctx.new_cell_sink(1);
+        let looked_up = c.cell().map_async(|a: &i32| { let a = *a; async move { a * 10 } });
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = looked_up.listen(move |b: &Option<i32>| out.lock().as_mut().unwrap().push(*b));
+        }
+        c.send(2);
+        c.send(3);
+        let futures: Vec<_> = spawned.lock().as_mut().unwrap().drain(..).collect();
+        assert_eq!(3, futures.len());
+        for future in futures.into_iter().rev() {
+            run_future(future);
+        }
+        assert_eq!(Some(30), looked_up.sample());
+        c.send(4);
+        assert_eq!(None, looked_up.sample());
+        l.unlisten();
+        assert_eq!(vec![None, None, None, Some(30), None], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
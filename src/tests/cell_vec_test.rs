@@ -0,0 +1,185 @@
+use crate::CellVecSink;
+use crate::SharedVec;
+use crate::SodiumCtx;
+use crate::VecDiff;
+use crate::tests::assert_memory_freed;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+#[test]
+fn map_filter_fold_process_only_diffs() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sink = CellVecSink::new(sodium_ctx, vec![1, 2, 3].into());
+        let map_calls = Arc::new(Mutex::new(0));
+        let doubled;
+        {
+            let map_calls = map_calls.clone();
+            doubled = sink.cell_vec().map(move |a: &i32| {
+                *map_calls.lock().unwrap() += 1;
+                a * 2
+            });
+        }
+        let evens = sink.cell_vec().filter(|a: &i32| a % 2 == 0);
+        let sum = sink.cell_vec().fold(0, |b: &i32, a: &i32| b + a, |b: &i32, a: &i32| b - a);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = evens.diffs().listen(move |diffs: &Vec<VecDiff<i32>>| out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        sodium_ctx.transaction(|| {
+            sink.push(4);
+            sink.remove(0);
+        });
+        sink.update(1, 5);
+        sink.insert(0, 6);
+        l.unlisten();
+        assert_eq!(vec![6, 2, 5, 4], sink.cell_vec().cell().sample().to_vec());
+        assert_eq!(vec![12, 4, 10, 8], doubled.cell().sample().to_vec());
+        assert_eq!(vec![6, 2, 4], evens.cell().sample().to_vec());
+        assert_eq!(17, sum.sample());
+        // 3 for the initial vec, then one per inserted or updated element
+        assert_eq!(6, *map_calls.lock().unwrap());
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<VecDiff<i32>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![VecDiff::Insert { index: 1, value: 4 }],
+                    vec![VecDiff::Insert { index: 0, value: 6 }]
+                ],
+                *out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn map_reports_old_values_on_remove_and_update() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sink = CellVecSink::new(sodium_ctx, vec![1, 2, 3].into());
+        let doubled = sink.cell_vec().map(|a: &i32| a * 2);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = doubled.diffs().listen(move |diffs: &Vec<VecDiff<i32>>| out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        sink.remove(0);
+        sodium_ctx.transaction(|| {
+            sink.update(1, 5);
+            sink.update(1, 6);
+        });
+        l.unlisten();
+        assert_eq!(vec![4, 12], doubled.cell().sample().to_vec());
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<VecDiff<i32>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![VecDiff::Remove { index: 0, value: 2 }],
+                    vec![
+                        VecDiff::Update { index: 1, old: 6, new: 10 },
+                        VecDiff::Update { index: 1, old: 10, new: 12 }
+                    ]
+                ],
+                *out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn filter_shifts_indexes_past_dropped_elements() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sink = CellVecSink::new(sodium_ctx, vec![1, 2, 3, 4, 5, 6].into());
+        let evens = sink.cell_vec().filter(|a: &i32| a % 2 == 0);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = evens.diffs().listen(move |diffs: &Vec<VecDiff<i32>>| out.lock().as_mut().unwrap().push(diffs.clone()));
+        }
+        // [2, 3, 4, 5, 6]: the dropped 1 goes without a diff
+        sink.remove(0);
+        // [7, 3, 4, 5, 6]
+        sink.update(0, 7);
+        // [7, 3, 8, 5, 6]
+        sink.update(2, 8);
+        // [7, 10, 8, 5, 6]
+        sink.update(1, 10);
+        // [7, 10, 8, 5]
+        sink.remove(4);
+        l.unlisten();
+        assert_eq!(vec![10, 8], evens.cell().sample().to_vec());
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<VecDiff<i32>>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![VecDiff::Remove { index: 0, value: 2 }],
+                    vec![VecDiff::Update { index: 0, old: 4, new: 8 }],
+                    vec![VecDiff::Insert { index: 0, value: 10 }],
+                    vec![VecDiff::Remove { index: 2, value: 6 }]
+                ],
+                *out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+// counts its clones, to catch the vec being copied
+struct Counted {
+    value: i32,
+    clones: Arc<AtomicUsize>
+}
+
+impl Clone for Counted {
+    fn clone(&self) -> Self {
+        self.clones.fetch_add(1, Ordering::SeqCst);
+        Counted { value: self.value, clones: self.clones.clone() }
+    }
+}
+
+#[test]
+fn change_to_large_vec_does_not_copy_it() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let init: SharedVec<Counted> = (0..100_000).map(|value| Counted { value, clones: clones.clone() }).collect();
+        let sink = CellVecSink::new(sodium_ctx, init);
+        let doubled = sink.cell_vec().map(|a: &Counted| a.value * 2);
+        let evens = sink.cell_vec().filter(|a: &Counted| a.value % 2 == 0);
+        let sum = sink.cell_vec().fold(0i64, |b: &i64, a: &Counted| b + a.value as i64, |b: &i64, a: &Counted| b - a.value as i64);
+        // an old version held onto must be left as it was
+        let before = sink.cell_vec().cell().sample();
+        clones.store(0, Ordering::SeqCst);
+        sink.update(50_000, Counted { value: 1, clones: clones.clone() });
+        sink.remove(0);
+        sink.insert(99_000, Counted { value: 4, clones: clones.clone() });
+        let copies = clones.load(Ordering::SeqCst);
+        assert!(copies < 1_000, "{} elements were cloned", copies);
+        assert_eq!(50_000, before[50_000].value);
+        let after = sink.cell_vec().cell().sample();
+        assert_eq!(100_000, after.len());
+        assert_eq!(1, after[49_999].value);
+        assert_eq!(4, after[99_000].value);
+        assert_eq!(Some(&2), doubled.cell().sample().get(49_999));
+        assert_eq!(49_999, evens.cell().sample().len());
+        assert_eq!(4_999_950_000 - 50_000 + 1 + 4, sum.sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
@@ -0,0 +1,25 @@
+use crate::testing::LawCheck;
+use crate::testing::LawGraph;
+
+#[test]
+fn laws_hold() {
+    LawCheck::new(0).cases(200).check_all();
+}
+
+#[test]
+fn filter_fusion() {
+    LawCheck::new(1000).check("filter fusion", |graph: &mut LawGraph| {
+        let s = graph.stream();
+        (s.filter(|a: &i32| *a > 2).filter(|a: &i32| *a < 7), s.filter(|a: &i32| *a > 2 && *a < 7))
+    });
+}
+
+#[test]
+#[should_panic(expected = "law \"or_else is commutative\" does not hold for seed")]
+fn reports_broken_law() {
+    LawCheck::new(0).inputs(2, 1).check("or_else is commutative", |graph: &mut LawGraph| {
+        let s1 = graph.input_stream();
+        let s2 = graph.input_stream();
+        (s1.or_else(&s2), s2.or_else(&s1))
+    });
+}
@@ -0,0 +1,180 @@
+use crate::Listener;
+use crate::Listeners;
+use crate::SodiumCtx;
+use crate::tests::assert_memory_freed;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[test]
+fn guard_unlistens_on_drop() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        {
+            let _guard;
+            {
+                let out = out.clone();
+                _guard =
+                    s.stream()
+                        .listen(
+                            move |a: &i32|
+                                out.lock().as_mut().unwrap().push(*a)
+                        )
+                        .into_guard();
+            }
+            s.send(1);
+        }
+        s.send(2);
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![1], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn listeners_unlisten_all() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s1 = sodium_ctx.new_stream_sink();
+        let s2 = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut listeners = Listeners::new();
+        {
+            let out = out.clone();
+            listeners.push(
+                s1.stream().listen(
+                    move |a: &i32|
+                        out.lock().as_mut().unwrap().push(*a)
+                )
+            );
+        }
+        {
+            let out = out.clone();
+            listeners.push(
+                s2.stream().listen(
+                    move |a: &i32|
+                        out.lock().as_mut().unwrap().push(*a * 10)
+                )
+            );
+        }
+        s1.send(1);
+        s2.send(2);
+        assert_eq!(2, listeners.len());
+        listeners.unlisten();
+        assert!(listeners.is_empty());
+        s1.send(3);
+        s2.send(4);
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![1, 20], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn listeners_unlisten_on_drop() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        {
+            let mut listeners = Listeners::new();
+            let out = out.clone();
+            listeners.push(s.stream().listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a)));
+            s.send(1);
+        }
+        s.send(2);
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![1], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn append() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l1;
+        let l2;
+        {
+            let out = out.clone();
+            l1 = s.stream().listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        {
+            let out = out.clone();
+            l2 = s.stream().map(|a: &i32| *a + 100).listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        let l = l1.append(&l2);
+        s.send(1);
+        l.unlisten();
+        s.send(2);
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![1, 101], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn combine() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut ls = Vec::new();
+        for i in 0..3 {
+            let out = out.clone();
+            ls.push(s.stream().listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a + i)));
+        }
+        let l = Listener::combine(sodium_ctx, ls);
+        s.send(10);
+        l.unlisten();
+        s.send(20);
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![10, 11, 12], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn add_cleanup() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    let cleaned_up = Arc::new(Mutex::new(false));
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let s2;
+        {
+            let cleaned_up = cleaned_up.clone();
+            s2 = s.stream().map(|a: &i32| *a * 2).add_cleanup(move || *cleaned_up.lock().unwrap() = true);
+        }
+        let l = s2.listen(|_: &i32| {});
+        s.send(1);
+        assert!(!*cleaned_up.lock().unwrap());
+        l.unlisten();
+    }
+    assert_memory_freed(sodium_ctx);
+    assert!(*cleaned_up.lock().unwrap());
+}
@@ -0,0 +1,31 @@
+use crate::testing::MarbleTest;
+use crate::SodiumCtx;
+use crate::tests::assert_memory_freed;
+
+#[test]
+fn merge_simultaneous() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let mut marble_test = MarbleTest::new(sodium_ctx);
+        let s1 = marble_test.stream_input_with_coalescer("a b (cd) -", |_l, r| *r);
+        let s2 = marble_test.stream_input("x - y    z");
+        marble_test.expect(&s2.or_else(&s1), "x b y z");
+        marble_test.expect(&s1.merge(&s2, |l: &char, r: &char| if l < r { *l } else { *r }), "a b d z");
+        marble_test.expect(&s1.map(|c: &char| c.to_ascii_uppercase()), "A B D");
+        marble_test.expect_cell(&s1.hold('-'), "a b d d");
+        marble_test.expect_cell(&s1.accum(0, |_: &char, n: &i32| n + 1), "1 2 3 3");
+        marble_test.run();
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+#[should_panic(expected = "stream #0 did not match its marble diagram")]
+fn reports_mismatch() {
+    let sodium_ctx = SodiumCtx::new();
+    let mut marble_test = MarbleTest::new(&sodium_ctx);
+    let s1 = marble_test.stream_input("a-b");
+    marble_test.expect(&s1, "a-c");
+    marble_test.run();
+}
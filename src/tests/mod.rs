@@ -1,11 +1,27 @@
 mod cell_loop_test;
+mod cell_map_test;
 mod cell_test;
+mod cell_vec_test;
+#[cfg(feature = "testing")]
+mod laws_test;
+mod listener_test;
+#[cfg(feature = "testing")]
+mod marble_test;
 mod mem_test;
 mod node_test;
+mod recorder_test;
 mod stream_test;
 
 use crate::SodiumCtx;
 
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::{Context, Wake, Waker};
+
+pub type Spawned = Arc<Mutex<Vec<Pin<Box<dyn Future<Output=()>+Send>>>>>;
+
 fn init() {
     let _ = env_logger::builder().is_test(true).try_init();
 }
@@ -19,3 +35,26 @@ pub fn assert_memory_freed(sodium_ctx: &SodiumCtx) {
     println!("node_ref_count {}", node_ref_count);
     assert_eq!(node_count, 0);
 }
+
+// keeps the futures spawned by map_async, so a test can run them in whatever order it likes
+pub fn queue_futures(sodium_ctx: &SodiumCtx) -> Spawned {
+    let spawned: Spawned = Arc::new(Mutex::new(Vec::new()));
+    {
+        let spawned = spawned.clone();
+        sodium_ctx.set_async_spawner(move |future| spawned.lock().as_mut().unwrap().push(future));
+    }
+    spawned
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+// only for futures that never wait on anything
+pub fn run_future(mut future: Pin<Box<dyn Future<Output=()>+Send>>) {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    while future.as_mut().poll(&mut cx).is_pending() {}
+}
@@ -1,4 +1,5 @@
 
+use crate::impl_::gc_node::{GcCtx,GcNode,Tracer};
 use crate::impl_::sodium_ctx::SodiumCtx;
 use crate::impl_::node::{IsNode,Node};
 use crate::tests::init;
@@ -115,3 +116,20 @@ pub fn assert_memory_freed(sodium_ctx: &SodiumCtx) {
     println!("node_ref_count {}", node_ref_count);
     assert_eq!(node_count, 0);
 }
+
+#[test]
+fn failed_collection_leaves_the_graph_as_it_was() {
+    init();
+    let gc_ctx = GcCtx::new();
+    let b = GcNode::new(&gc_ctx, "b", || {}, |_tracer: &mut Tracer| {});
+    // a traces b twice while b only counts one reference
+    let a = GcNode::new(&gc_ctx, "a", || {}, move |tracer: &mut Tracer| {
+        tracer(&b);
+        tracer(&b);
+    });
+    a.inc_ref();
+    a.dec_ref();
+    assert!(gc_ctx.try_collect_cycles().is_err());
+    // a is still buffered as a root, so the next pass finds the same problem
+    assert!(gc_ctx.try_collect_cycles().is_err());
+}
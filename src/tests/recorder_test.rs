@@ -0,0 +1,72 @@
+use crate::Recorder;
+use crate::Replayer;
+use crate::SodiumCtx;
+use crate::SodiumError;
+use crate::tests::assert_memory_freed;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[test]
+fn record_and_replay() {
+    let decode = |s: &str| s.parse::<i32>().map_err(|_| SodiumError::Replay(format!("bad i32 {}", s)));
+    let lines;
+    {
+        let sodium_ctx = SodiumCtx::new();
+        let recorder = Recorder::new(&sodium_ctx);
+        let s = recorder.stream_sink("s", &sodium_ctx.new_stream_sink(), |a: &i32| a.to_string());
+        let c = recorder.cell_sink("c", &sodium_ctx.new_cell_sink("a".to_string()), |a: &String| a.clone());
+        s.send(1);
+        sodium_ctx.transaction(|| {
+            c.send("b\tc".to_string());
+            s.send(2);
+        });
+        lines = recorder.lines();
+        // the first transaction went to creating the cell sink
+        assert_eq!(vec!["2\t0\ts\t1", "3\t1\tc\tb\\tc", "3\t2\ts\t2"], lines);
+    }
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let c = sodium_ctx.new_cell_sink("a".to_string());
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.stream().snapshot(&c.cell(), |a: &i32, b: &String| format!("{} {}", a, b)).listen(move |x: &String| out.lock().as_mut().unwrap().push(x.clone()));
+        }
+        let mut replayer = Replayer::parse(sodium_ctx, lines.iter().map(|line: &String| line.as_str())).unwrap();
+        assert_eq!(Err(SodiumError::Replay("no sink registered for s".to_string())), replayer.replay());
+        replayer.stream_sink("s", &s, decode);
+        replayer.cell_sink("c", &c, |s: &str| Ok(s.to_string()));
+        assert_eq!(2, replayer.transaction_count());
+        assert_eq!(Ok(()), replayer.replay());
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<String> = l.as_ref().unwrap();
+            assert_eq!(vec!["1 a", "2 a"], *out);
+        }
+        assert_eq!("b\tc", c.cell().sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn replay_sends_nothing_from_a_transaction_that_fails_to_decode() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let c1 = sodium_ctx.new_cell_sink(0);
+        let c2 = sodium_ctx.new_cell_sink(0);
+        let decode = |s: &str| s.parse::<i32>().map_err(|_| SodiumError::Replay(format!("bad i32 {}", s)));
+        let mut replayer = Replayer::parse(sodium_ctx, vec!["1\t0\tc1\t1", "1\t1\tc2\tx"]).unwrap();
+        replayer.cell_sink("c1", &c1, decode);
+        replayer.cell_sink("c2", &c2, decode);
+        assert_eq!(Err(SodiumError::Replay("bad i32 x".to_string())), replayer.replay());
+        assert_eq!(0, c1.cell().sample());
+        assert_eq!(0, c2.cell().sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
@@ -1,14 +1,28 @@
 use crate::Cell;
+use crate::Either;
+use crate::ManualClock;
+use crate::NodeProfile;
 use crate::lambda1;
 use crate::Operational;
+use crate::SodiumError;
+use crate::PoisonPolicy;
 use crate::SodiumCtx;
 use crate::Stream;
 use crate::StreamSink;
+use crate::TransactionStats;
+use crate::impl_::sodium_ctx::SodiumCtx as SodiumCtxImpl;
 use crate::tests::assert_memory_freed;
 use crate::tests::init;
+use crate::tests::queue_futures;
+use crate::tests::run_future;
 
+use std::collections::HashMap;
+use std::panic;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn map() {
@@ -804,3 +818,862 @@ fn loop_cell() {
     }
     assert_memory_freed(sodium_ctx);
 }
+
+#[test]
+fn snapshot3() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    sodium_ctx.set_panic_on_untracked_deps(true);
+    {
+        let sa = sodium_ctx.new_stream_sink();
+        let cb = sodium_ctx.new_cell_sink(2);
+        let cc = sodium_ctx.new_cell_sink(3);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l =
+                sa.stream()
+                    .snapshot3(&cb.cell(), &cc.cell(), |a: &i32, b: &i32, c: &i32| *a * 100 + *b * 10 + *c)
+                    .listen(
+                        move |a: &i32|
+                            out.lock().as_mut().unwrap().push(*a)
+                    );
+        }
+        sa.send(1);
+        cc.send(4);
+        sa.send(5);
+        l.unlisten();
+        {
+            let lock = out.lock();
+            let out: &Vec<i32> = lock.as_ref().unwrap();
+            assert_eq!(vec![123, 524], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "without declaring it as a dependency")]
+fn untracked_sample_panics() {
+    let sodium_ctx = SodiumCtx::new();
+    sodium_ctx.set_panic_on_untracked_deps(true);
+    let sa = sodium_ctx.new_stream_sink();
+    let cb = sodium_ctx.new_cell(2);
+    let _l = sa.stream().map(move |a: &i32| *a + cb.sample()).listen(|_: &i32| {});
+    sa.send(1);
+}
+
+#[test]
+fn recovers_after_listener_panic() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    sodium_ctx.set_poison_policy(PoisonPolicy::Recover);
+    {
+        let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let c = s.stream().hold(0);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.stream().listen(move |a: &i32| {
+                if *a == 2 {
+                    panic!("listener failed");
+                }
+                out.lock().as_mut().unwrap().push(*a);
+            });
+        }
+        s.send(1);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| s.send(2))).is_err());
+        s.send(3);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![1, 3], *out);
+        }
+        assert_eq!(3, c.sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn recovered_panic_discards_transaction() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    sodium_ctx.set_poison_policy(PoisonPolicy::Recover);
+    {
+        let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let c = s.stream().hold(0);
+        // listens after the hold has taken the new value
+        let l = c.updates().map(|a: &i32| *a).listen(|a: &i32| {
+            if *a == 2 {
+                panic!("listener failed");
+            }
+        });
+        s.send(1);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| s.send(2))).is_err());
+        // the aborted transaction is cleaned up when the next one opens
+        sodium_ctx.transaction(|| {});
+        assert_eq!(1, c.sample());
+        s.send(3);
+        l.unlisten();
+        assert_eq!(3, c.sample());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn propagates_listener_panic() {
+    let sodium_ctx = SodiumCtx::new();
+    let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+    let _l = s.stream().listen(|a: &i32| {
+        if *a == 2 {
+            panic!("listener failed");
+        }
+    });
+    assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| s.send(2))).is_err());
+    assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| s.send(3))).is_err());
+}
+
+#[test]
+fn switch_s_keyed() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sa: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let sb: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let sc: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let init: HashMap<&'static str,Stream<i32>> = vec![("a", sa.stream()), ("b", sb.stream())].into_iter().collect();
+        let csw = sodium_ctx.new_cell_sink(init);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = Cell::switch_s_keyed(&csw.cell()).listen(move |x: &HashMap<&'static str,i32>| {
+                let mut x: Vec<(&'static str,i32)> = x.iter().map(|(k, v)| (*k, *v)).collect();
+                x.sort();
+                out.lock().as_mut().unwrap().push(x);
+            });
+        }
+        sodium_ctx.transaction(|| {
+            sa.send(1);
+            sb.send(2);
+        });
+        sc.send(3);
+        csw.send(vec![("b", sb.stream()), ("c", sc.stream())].into_iter().collect());
+        sa.send(4);
+        sc.send(5);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<(&'static str,i32)>> = l.as_ref().unwrap();
+            assert_eq!(vec![vec![("a", 1), ("b", 2)], vec![("c", 5)]], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn switch_c_keyed() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let ca = sodium_ctx.new_cell_sink(1);
+        let cb = sodium_ctx.new_cell_sink(2);
+        let cc = sodium_ctx.new_cell_sink(3);
+        let csw = sodium_ctx.new_cell_sink(vec![("a", ca.cell()), ("b", cb.cell())]);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = Cell::switch_c_keyed(&csw.cell()).listen(move |x: &Vec<(&'static str,i32)>| out.lock().as_mut().unwrap().push(x.clone()));
+        }
+        ca.send(4);
+        sodium_ctx.transaction(|| {
+            csw.send(vec![("c", cc.cell()), ("b", cb.cell())]);
+            cc.send(5);
+        });
+        ca.send(6);
+        cb.send(7);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<Vec<(&'static str,i32)>> = l.as_ref().unwrap();
+            assert_eq!(
+                vec![
+                    vec![("a", 1), ("b", 2)],
+                    vec![("a", 4), ("b", 2)],
+                    vec![("c", 5), ("b", 2)],
+                    vec![("c", 5), ("b", 7)]
+                ],
+                *out
+            );
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn send_all() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let c = sodium_ctx.new_cell_sink(0);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.stream().snapshot(&c.cell(), |a: &i32, b: &i32| a + b).listen(move |x: &i32| out.lock().as_mut().unwrap().push(*x));
+        }
+        s.send_all(vec![1, 2, 3]);
+        c.send_all(vec![10, 20]);
+        s.send_all(vec![4]);
+        assert_eq!(
+            Err(SodiumError::SendAllInTransaction),
+            sodium_ctx.transaction(|| s.try_send_all(vec![5, 6]))
+        );
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![1, 2, 3, 24], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn send_all_keeps_other_threads_out_of_the_batch() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s1 = sodium_ctx.new_stream_sink();
+        let s2 = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let (started_tx, started_rx) = mpsc::channel();
+        let l;
+        {
+            let out = out.clone();
+            let started_tx = Mutex::new(started_tx);
+            l = s1.stream().or_else(&s2.stream()).listen(move |a: &i32| {
+                let _ = started_tx.lock().unwrap().send(());
+                thread::sleep(Duration::from_millis(5));
+                out.lock().as_mut().unwrap().push(*a);
+            });
+        }
+        let handle;
+        {
+            let s2 = s2.clone();
+            // sends once the batch is under way
+            handle = thread::spawn(move || {
+                started_rx.recv().unwrap();
+                s2.send(100);
+            });
+        }
+        s1.send_all(vec![1, 2, 3, 4]);
+        handle.join().unwrap();
+        l.unlisten();
+        assert_eq!(vec![1, 2, 3, 4, 100], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "send called more than once in a transaction on a sink without a coalescer")]
+fn send_twice_without_coalescer_panics() {
+    let sodium_ctx = SodiumCtx::new();
+    let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+    sodium_ctx.transaction(|| {
+        s.send(1);
+        s.send(2);
+    });
+}
+
+#[test]
+fn transaction_guard() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s1 = sodium_ctx.new_stream_sink();
+        let s2 = sodium_ctx.new_stream_sink();
+        let c = s1.stream().hold(0);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s1.stream().or_else(&s2.stream()).listen(move |x: &i32| out.lock().as_mut().unwrap().push(*x));
+        }
+        let send_both = |a: i32| {
+            s2.send(a + 1);
+            s1.send(a);
+        };
+        {
+            let _transaction = sodium_ctx.begin();
+            send_both(1);
+        }
+        let transaction = sodium_ctx.begin();
+        send_both(3);
+        assert_eq!(Ok(()), transaction.commit());
+        let transaction = sodium_ctx.begin();
+        send_both(5);
+        {
+            let nested = sodium_ctx.begin();
+            assert_eq!(Err(SodiumError::AbortNested), nested.abort());
+        }
+        assert_eq!(Ok(()), transaction.abort());
+        assert_eq!(3, c.sample());
+        send_both(8);
+        l.unlisten();
+        {
+            let l = out.lock();
+            let out: &Vec<i32> = l.as_ref().unwrap();
+            assert_eq!(vec![1, 3, 9, 8], *out);
+        }
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn transaction_hooks() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook = |name: &'static str| {
+            let events = events.clone();
+            move |stats: &TransactionStats| events.lock().as_mut().unwrap().push((name, stats.clone()))
+        };
+        let s = sodium_ctx.new_stream_sink();
+        let l = s.stream().map(|a: &i32| a + 1).listen(|_: &i32| {});
+        sodium_ctx.on_transaction_start(hook("start"));
+        sodium_ctx.on_propagation_done(hook("propagation done"));
+        sodium_ctx.on_transaction_end(hook("end"));
+        sodium_ctx.on_gc(hook("gc"));
+        s.send(1);
+        l.unlisten();
+        let l = events.lock();
+        let events: &Vec<(&'static str,TransactionStats)> = l.as_ref().unwrap();
+        let names: Vec<&'static str> = events.iter().map(|(name, _)| *name).collect();
+        assert_eq!(vec!["start", "propagation done", "end", "gc"], names);
+        let transaction_id = events[0].1.transaction_id;
+        assert_eq!(TransactionStats { transaction_id, nodes_updated: 2, listeners_fired: 1, sinks_sent: 1 }, events[3].1);
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn transaction_hooks_after_abort_and_batch() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook = |name: &'static str| {
+            let events = events.clone();
+            move |stats: &TransactionStats| events.lock().as_mut().unwrap().push((name, stats.clone()))
+        };
+        let s = sodium_ctx.new_stream_sink();
+        let l = s.stream().listen(|_: &i32| {});
+        sodium_ctx.on_transaction_end(hook("end"));
+        sodium_ctx.on_gc(hook("gc"));
+        let transaction = sodium_ctx.begin();
+        s.send(1);
+        assert_eq!(Ok(()), transaction.abort());
+        s.send_all(vec![2, 3]);
+        l.unlisten();
+        let l = events.lock();
+        let events: &Vec<(&'static str,TransactionStats)> = l.as_ref().unwrap();
+        let names: Vec<&'static str> = events.iter().map(|(name, _)| *name).collect();
+        assert_eq!(vec!["end", "end", "end", "gc"], names);
+        assert_eq!(1, events[0].1.sinks_sent);
+        assert_eq!(0, events[0].1.listeners_fired);
+        // the one collection for the batch follows its last transaction
+        assert_eq!(events[2].1, events[3].1);
+        assert_eq!(1, events[3].1.listeners_fired);
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn profiling() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let l =
+            s.stream()
+                .map(|a: &i32| { thread::sleep(Duration::from_millis(10)); a + 1 })
+                .named("slow")
+                .merge(&s.stream().filter(|a: &i32| a % 2 == 0), |a: &i32, _: &i32| *a)
+                .listen(|_: &i32| {});
+        s.send(1);
+        assert!(sodium_ctx.profile_report().is_empty());
+        sodium_ctx.set_profiling(true);
+        s.send(2);
+        s.send(3);
+        sodium_ctx.set_profiling(false);
+        s.send(4);
+        let report = sodium_ctx.profile_report();
+        let names: Vec<(&str,Option<&str>,u64)> = report.iter().map(|profile: &NodeProfile| (profile.name.as_str(), profile.label.as_deref(), profile.updates)).collect();
+        assert_eq!(vec![("Stream::filter", None, 2), ("Stream::listen", None, 2), ("Stream::map", Some("slow"), 2), ("Stream::merge", None, 2)], names);
+        let map = report.iter().find(|profile: &&NodeProfile| profile.label.as_deref() == Some("slow")).unwrap();
+        assert!(map.total_time >= Duration::from_millis(20));
+        sodium_ctx.clear_profile();
+        assert!(sodium_ctx.profile_report().is_empty());
+        sodium_ctx.set_profiling(true);
+        s.send(5);
+        sodium_ctx.set_profiling(false);
+        assert_eq!(4, sodium_ctx.profile_report().len());
+        // the entries go with their nodes
+        l.unlisten();
+        assert!(sodium_ctx.profile_report().is_empty());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn timer_operators() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clock = ManualClock::new();
+        sodium_ctx.set_clock(clock.clone());
+        let s = sodium_ctx.new_stream_sink();
+        let record = |sa: &Stream<i32>| {
+            let out: Arc<Mutex<Vec<(u128,i32)>>> = Arc::new(Mutex::new(Vec::new()));
+            let l;
+            {
+                let out = out.clone();
+                let sodium_ctx = sodium_ctx.impl_.clone();
+                l = sa.listen(move |a: &i32| out.lock().as_mut().unwrap().push((sodium_ctx.now().as_millis(), *a)));
+            }
+            (out, l)
+        };
+        let (delayed, l1) = record(&s.stream().delay(Duration::from_millis(100)));
+        let (debounced, l2) = record(&s.stream().debounce(Duration::from_millis(100)));
+        let (throttled, l3) = record(&s.stream().throttle(Duration::from_millis(100)));
+        s.send(1);
+        clock.advance(Duration::from_millis(50));
+        sodium_ctx.run_timers();
+        s.send(2);
+        clock.advance(Duration::from_millis(30));
+        s.send(3);
+        assert_eq!(Some(Duration::from_millis(20)), sodium_ctx.time_to_next_timer());
+        clock.advance(Duration::from_millis(20));
+        sodium_ctx.run_timers();
+        clock.advance(Duration::from_millis(100));
+        sodium_ctx.run_timers();
+        assert_eq!(None, sodium_ctx.time_to_next_timer());
+        l1.unlisten();
+        l2.unlisten();
+        l3.unlisten();
+        assert_eq!(vec![(100, 1), (150, 2), (180, 3)], *delayed.lock().unwrap());
+        assert_eq!(vec![(180, 3)], *debounced.lock().unwrap());
+        assert_eq!(vec![(0, 1), (100, 3)], *throttled.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn timers_run_in_due_order() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clock = ManualClock::new();
+        sodium_ctx.set_clock(clock.clone());
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut ls = Vec::new();
+        for delay in [300, 100, 200, 100] {
+            let out = out.clone();
+            ls.push(s.stream().delay(Duration::from_millis(delay)).listen(move |a: &i32| out.lock().as_mut().unwrap().push((delay, *a))));
+        }
+        s.send(1);
+        clock.advance(Duration::from_millis(250));
+        sodium_ctx.run_timers();
+        assert_eq!(Some(Duration::from_millis(50)), sodium_ctx.time_to_next_timer());
+        clock.advance(Duration::from_millis(50));
+        sodium_ctx.run_timers();
+        for l in ls {
+            l.unlisten();
+        }
+        // the two due at 100 run in the order they were scheduled
+        assert_eq!(vec![(100, 1), (100, 1), (200, 1), (300, 1)], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn pending_timers_do_not_keep_the_context_alive() {
+    let sodium_ctx = SodiumCtx::new();
+    let weak_sodium_ctx = SodiumCtxImpl::downgrade(&sodium_ctx.impl_);
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let l = sodium_ctx.new_cell(1).sample_every(Duration::from_secs(1)).listen(|_: &i32| {});
+        let l2 = s.stream().throttle(Duration::from_secs(1)).listen(|_: &i32| {});
+        s.send(1);
+        assert!(sodium_ctx.time_to_next_timer().is_some());
+        l.unlisten();
+        l2.unlisten();
+    }
+    sodium_ctx.impl_.collect_cycles();
+    drop(sodium_ctx);
+    assert!(weak_sodium_ctx.upgrade().is_none());
+}
+
+#[test]
+fn windows() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clock = ManualClock::new();
+        sodium_ctx.set_clock(clock.clone());
+        let s = sodium_ctx.new_stream_sink();
+        let by_count = Arc::new(Mutex::new(Vec::new()));
+        let by_time = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let by_count = by_count.clone();
+            let by_time = by_time.clone();
+            l = s.stream().window_count(2).listen(move |window: &Vec<i32>| by_count.lock().as_mut().unwrap().push(window.clone()))
+                .append(&s.stream().window_time(Duration::from_secs(1)).listen(move |window: &Vec<i32>| by_time.lock().as_mut().unwrap().push(window.clone())));
+        }
+        s.send(1);
+        s.send(2);
+        s.send(3);
+        clock.advance(Duration::from_secs(1));
+        sodium_ctx.run_timers();
+        clock.advance(Duration::from_secs(1));
+        sodium_ctx.run_timers();
+        s.send(4);
+        clock.advance(Duration::from_secs(1));
+        sodium_ctx.run_timers();
+        l.unlisten();
+        assert_eq!(vec![vec![1, 2], vec![3, 4]], *by_count.lock().unwrap());
+        assert_eq!(vec![vec![1, 2, 3], vec![4]], *by_time.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn sliding_fold() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let sum = s.stream().sliding_fold(3, 0, |a: &i32, sum: &i32| sum + a, |a: &i32, sum: &i32| sum - a);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = sum.listen(move |sum: &i32| out.lock().as_mut().unwrap().push(*sum));
+        }
+        for a in [1, 2, 3, 4, 10] {
+            s.send(a);
+        }
+        l.unlisten();
+        assert_eq!(vec![0, 1, 3, 6, 9, 17], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn windows_roll_back_with_a_recovered_transaction() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    sodium_ctx.set_poison_policy(PoisonPolicy::Recover);
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let sum = s.stream().sliding_fold(2, 0, |a: &i32, sum: &i32| sum + a, |a: &i32, sum: &i32| sum - a);
+        let windows = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let windows = windows.clone();
+            l = sum.updates().listen(|sum: &i32| {
+                if *sum > 500 {
+                    panic!("listener failed");
+                }
+            }).append(&s.stream().window_count(2).listen(move |window: &Vec<i32>| {
+                if window.contains(&999) {
+                    panic!("listener failed");
+                }
+                windows.lock().as_mut().unwrap().push(window.clone());
+            }));
+        }
+        s.send(1);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| s.send(999))).is_err());
+        sodium_ctx.transaction(|| {});
+        for a in [2, 3, 4] {
+            s.send(a);
+        }
+        l.unlisten();
+        assert_eq!(7, sum.sample());
+        assert_eq!(vec![vec![1, 2], vec![3, 4]], *windows.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn map_async() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let spawned = queue_futures(sodium_ctx);
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.stream().map_async(|a: &i32| { let a = *a; async move { a * 10 } }).listen(move |b: &i32| out.lock().as_mut().unwrap().push(*b));
+        }
+        s.send(1);
+        s.send(2);
+        // the later lookup finishes first, the earlier one's result is stale by the time it arrives
+        let futures: Vec<_> = spawned.lock().as_mut().unwrap().drain(..).collect();
+        for future in futures.into_iter().rev() {
+            run_future(future);
+        }
+        s.send(3);
+        let futures: Vec<_> = spawned.lock().as_mut().unwrap().drain(..).collect();
+        for future in futures {
+            run_future(future);
+        }
+        l.unlisten();
+        assert_eq!(vec![20, 30], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+#[should_panic(expected = "map_async needs an async spawner")]
+fn map_async_without_spawner_panics() {
+    let sodium_ctx = SodiumCtx::new();
+    let s: StreamSink<i32> = sodium_ctx.new_stream_sink();
+    let _ = s.stream().map_async(|a: &i32| { let a = *a; async move { a } });
+}
+
+#[test]
+fn switch_map() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clicks = sodium_ctx.new_stream_sink();
+        let responses: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let rs = responses.stream();
+            let rs_dep = rs.to_dep();
+            l = clicks.stream()
+                .switch_map(lambda1(move |m: &i32| { let m = *m; rs.map(move |r: &i32| r * m) }, vec![rs_dep]))
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        clicks.send(2);
+        responses.send(1);
+        clicks.send(3);
+        responses.send(1);
+        sodium_ctx.impl_.collect_cycles();
+        let node_count = sodium_ctx.impl_.node_count();
+        // the streams switched away from are collected
+        for m in 4..14 {
+            clicks.send(m);
+        }
+        sodium_ctx.impl_.collect_cycles();
+        assert_eq!(node_count, sodium_ctx.impl_.node_count());
+        responses.send(1);
+        l.unlisten();
+        assert_eq!(vec![2, 3, 13], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_map() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clicks = sodium_ctx.new_stream_sink();
+        let responses: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let rs = responses.stream();
+            let rs_dep = rs.to_dep();
+            l = clicks.stream()
+                .merge_map(lambda1(move |m: &i32| { let m = *m; rs.map(move |r: &i32| r * m) }, vec![rs_dep]), |a: &i32, b: &i32| a * 100 + b)
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        clicks.send(2);
+        responses.send(1);
+        clicks.send(3);
+        responses.send(1);
+        l.unlisten();
+        assert_eq!(vec![2, 203], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_map_keeps_nothing_per_event_but_the_inner_stream() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clicks: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let responses: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let rs = responses.stream();
+            let rs_dep = rs.to_dep();
+            l = clicks.stream()
+                .merge_map(lambda1(move |_: &i32| rs.clone(), vec![rs_dep]), |a: &i32, b: &i32| a + b)
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        clicks.send(0);
+        sodium_ctx.impl_.collect_cycles();
+        let node_count = sodium_ctx.impl_.node_count();
+        // f makes no streams of its own here, so nothing is left behind by each event
+        for _ in 0..10 {
+            clicks.send(0);
+        }
+        sodium_ctx.impl_.collect_cycles();
+        assert_eq!(node_count, sodium_ctx.impl_.node_count());
+        responses.send(1);
+        l.unlisten();
+        assert_eq!(vec![11], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_map_inner_streams_of_the_outer_stream_are_freed() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let clicks: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            let cs = clicks.stream();
+            let cs_dep = cs.to_dep();
+            l = clicks.stream()
+                .merge_map(lambda1(move |m: &i32| { let m = *m; cs.map(move |c: &i32| c * m) }, vec![cs_dep]), |a: &i32, b: &i32| a + b)
+                .listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        clicks.send(1);
+        clicks.send(2);
+        clicks.send(3);
+        l.unlisten();
+        assert_eq!(vec![2, 9], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn join_simultaneous() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sa: StreamSink<i32> = sodium_ctx.new_stream_sink();
+        let sb: StreamSink<&'static str> = sodium_ctx.new_stream_sink();
+        let joined = Arc::new(Mutex::new(Vec::new()));
+        let either = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let joined = joined.clone();
+            let either = either.clone();
+            l = sa.stream().join_simultaneous(&sb.stream()).listen(move |ab: &(Option<i32>,Option<&'static str>)| joined.lock().as_mut().unwrap().push(*ab))
+                .append(&sa.stream().merge_either(&sb.stream()).listen(move |ab: &Either<i32,&'static str>| either.lock().as_mut().unwrap().push(*ab)));
+        }
+        sa.send(1);
+        sb.send("x");
+        sodium_ctx.transaction(|| {
+            sb.send("y");
+            sa.send(2);
+        });
+        l.unlisten();
+        assert_eq!(vec![(Some(1), None), (None, Some("x")), (Some(2), Some("y"))], *joined.lock().unwrap());
+        assert_eq!(vec![Either::Left(1), Either::Right("x"), Either::Left(2)], *either.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn merge_with_itself() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let s = sodium_ctx.new_stream_sink();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.stream().merge(&s.stream(), |a: &i32, b: &i32| a + b).listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        s.send(3);
+        l.unlisten();
+        assert_eq!(vec![6], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn diamond_waits_for_both_sides() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let p = sodium_ctx.new_stream_sink();
+        let q = sodium_ctx.new_stream_sink();
+        // x is reached from p first, and pulls in y, which d also depends on
+        let y = q.stream().map(|v: &i32| v * 10);
+        let x = p.stream().merge(&y, |a: &i32, b: &i32| a + b);
+        let d = x.merge(&y, |a: &i32, b: &i32| a * 1000 + b);
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = d.listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        sodium_ctx.transaction(|| {
+            p.send(1);
+            q.send(2);
+        });
+        l.unlisten();
+        assert_eq!(vec![21020], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+
+#[test]
+fn loop_through_or_else() {
+    let mut sodium_ctx = SodiumCtx::new();
+    let sodium_ctx = &mut sodium_ctx;
+    {
+        let sa = sodium_ctx.new_stream_sink();
+        let s = sodium_ctx.transaction(|| {
+            let sl = sodium_ctx.new_stream_loop();
+            let s = sl.stream().or_else(&sa.stream());
+            sl.loop_(&s.filter(|a: &i32| *a > 100));
+            s
+        });
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let l;
+        {
+            let out = out.clone();
+            l = s.listen(move |a: &i32| out.lock().as_mut().unwrap().push(*a));
+        }
+        sa.send(1);
+        sa.send(2);
+        l.unlisten();
+        assert_eq!(vec![1, 2], *out.lock().unwrap());
+    }
+    assert_memory_freed(sodium_ctx);
+}
+